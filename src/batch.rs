@@ -0,0 +1,134 @@
+use crate::export::{self, NoteEvent};
+use crate::{detect_pitch, freq_to_midi, SAMPLE_RATE, SIZE};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct BatchOptions {
+    /// Tab for every MIDI note in the chosen key and tuning
+    pub tab_table: Vec<String>,
+    pub a4_reference: f32,
+    pub power_threshold: f32,
+    pub clarity_threshold: f32,
+    /// See `export::merge_short_rests`
+    pub rest_threshold: f32,
+}
+
+/// Writes a note list next to every WAV file in `dir`, e.g. `take.wav` -> `take.csv`.
+/// Files that can't be read are reported and skipped.
+pub fn run(dir: &Path, options: &BatchOptions) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_wav(path))
+        .collect();
+    paths.sort();
+
+    for (i, path) in paths.iter().enumerate() {
+        print!("[{}/{}] {}: ", i + 1, paths.len(), path.display());
+        match process_file(path, options) {
+            Ok((output, note_count)) => {
+                println!("{} notes written to {}", note_count, output.display())
+            }
+            Err(e) => println!("skipped, {}", e),
+        }
+    }
+}
+
+fn is_wav(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("wav"),
+        None => false,
+    }
+}
+
+fn process_file(path: &Path, options: &BatchOptions) -> Result<(PathBuf, usize), String> {
+    let (mut samples, sample_rate) = read_wav(path)?;
+    if sample_rate as usize != SAMPLE_RATE {
+        // the detection block covers a fixed number of samples, so match the live rate
+        print!("resampling {} Hz -> {} Hz, ", sample_rate, SAMPLE_RATE);
+        samples = resample(&samples, sample_rate as f64 / SAMPLE_RATE as f64);
+    }
+    let notes = detect_notes(&samples, options);
+    let output = path.with_extension("csv");
+    export::write_note_list(&output, &notes).map_err(|e| e.to_string())?;
+    let note_count = notes.iter().filter(|note| note.midi.is_some()).count();
+    Ok((output, note_count))
+}
+
+/// Reads a WAV file as mono samples in -1..=1, averaging the channels.
+fn read_wav(path: &Path) -> Result<(Vec<f32>, u32), String> {
+    let mut reader = hound::WavReader::open(path).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    let samples: Result<Vec<f32>, _> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect(),
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect()
+        }
+    };
+    let samples = samples.map_err(|e| e.to_string())?;
+
+    let channels = spec.channels.max(1) as usize;
+    let mono = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
+
+/// Linearly interpolates `samples`, taking `step` input samples per output sample.
+fn resample(samples: &[f32], step: f64) -> Vec<f32> {
+    // positions are computed in f64, f32 can't address every sample of long files
+    let output_len = (samples.len() as f64 / step) as usize;
+    (0..output_len)
+        .filter_map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let current = *samples.get(index)?;
+            let next = samples.get(index + 1).copied().unwrap_or(current);
+            Some(current + (next - current) * fraction)
+        })
+        .collect()
+}
+
+/// Detects a pitch for every block of `SIZE` samples at `SAMPLE_RATE` and merges
+/// consecutive blocks with the same note. Blocks without a pitch become rests,
+/// like in the live note list.
+fn detect_notes(samples: &[f32], options: &BatchOptions) -> Vec<NoteEvent> {
+    let block_duration = SIZE as f32 / SAMPLE_RATE as f32;
+    let mut notes: Vec<NoteEvent> = Vec::new();
+    for (i, block) in samples.chunks_exact(SIZE).enumerate() {
+        let time = i as f32 * block_duration;
+        let midi = detect_pitch(
+            block,
+            SAMPLE_RATE,
+            options.power_threshold,
+            options.clarity_threshold,
+        )
+        .map(|pitch| freq_to_midi(pitch.frequency, options.a4_reference));
+
+        match notes.last_mut() {
+            Some(note) if note.midi == midi => note.duration = time + block_duration - note.start,
+            _ => notes.push(NoteEvent {
+                start: time,
+                duration: block_duration,
+                midi,
+                tab: match midi {
+                    Some(midi) => options.tab_table[midi as usize].clone(),
+                    None => export::REST.to_owned(),
+                },
+            }),
+        }
+    }
+    export::merge_short_rests(notes, options.rest_threshold)
+}
@@ -0,0 +1,290 @@
+use crate::filter::{HighPass, LowPass};
+use crate::poly::{Pitch, PolyDetector};
+use crate::spectrogram::Analyser;
+use crate::{
+    apply_window, calc_level_decay, detect_pitch, midi_to_freq, soft_clip, PolyphonyMode,
+    WindowFunction, SAMPLE_RATE, SIZE,
+};
+use ringbuf::Consumer;
+use std::collections::VecDeque;
+use std::f32::consts::TAU;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the thread sleeps when no input is waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The part of the settings the detection thread works with, sent by the main
+/// thread every update.
+#[derive(Clone, Copy)]
+pub struct DetectionSettings {
+    pub hop_size: usize,
+    /// Only run detection on every Nth block
+    pub detection_interval: usize,
+    pub power_threshold: f32,
+    pub clarity_threshold: f32,
+    pub a4_reference: f32,
+    pub polyphony_mode: PolyphonyMode,
+    pub max_voices: usize,
+    pub soft_clip: bool,
+    pub window_function: WindowFunction,
+    pub high_pass_cutoff: Option<f32>,
+    pub low_pass_cutoff: Option<f32>,
+    pub test_tone: Option<f32>,
+    pub level_decay_time: f32,
+    /// Note held in keyboard input mode, replacing the detector if `Some`
+    pub keyboard_note: Option<Option<u8>>,
+    pub show_spectrogram: bool,
+}
+
+/// The outcome of one block of `hop_size` samples.
+pub struct Block {
+    /// Whether this block was analysed, see `detection_interval`
+    pub was_analysed: bool,
+    /// Strongest first
+    pub pitches: Vec<Pitch>,
+    /// Input level at the end of the block
+    pub level: f32,
+    /// How full the input ring buffer was when the block was taken from it, 0..=1
+    pub ring_buffer_fill: f32,
+    /// Magnitude spectrum of the block if the spectrogram is shown
+    pub spectrum: Option<Vec<f32>>,
+}
+
+/// Drains the input and runs detection off the render thread. The thread stops
+/// when this is dropped.
+pub struct DetectionThread {
+    settings: Option<Sender<DetectionSettings>>,
+    pub blocks: Receiver<Block>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DetectionThread {
+    /// Nothing is processed until the first `send_settings`. With `has_input`
+    /// false, silence is generated in real time instead, so blocks keep coming
+    /// for keyboard input.
+    pub fn spawn(consumer: Consumer<f32>, has_input: bool) -> Self {
+        let (settings_sender, settings_receiver) = mpsc::channel();
+        let (block_sender, block_receiver) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("detection".to_owned())
+            .spawn(move || {
+                let settings = match settings_receiver.recv() {
+                    Ok(settings) => settings,
+                    Err(_) => return,
+                };
+                let mut detection = Detection::new(consumer, has_input, settings);
+                detection.run(&settings_receiver, &block_sender);
+            })
+            .unwrap();
+        Self {
+            settings: Some(settings_sender),
+            blocks: block_receiver,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn send_settings(&self, settings: DetectionSettings) {
+        if let Some(sender) = &self.settings {
+            sender.send(settings).ok();
+        }
+    }
+}
+
+impl Drop for DetectionThread {
+    fn drop(&mut self) {
+        // the thread exits once it notices the settings channel is closed
+        self.settings = None;
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                println!("detection thread panicked");
+            }
+        }
+    }
+}
+
+struct Detection {
+    consumer: Consumer<f32>,
+    has_input: bool,
+    settings: DetectionSettings,
+    buf: VecDeque<f32>,
+    poly_detector: PolyDetector,
+    analyser: Analyser,
+    high_pass: HighPass,
+    low_pass: LowPass,
+    test_tone_phase: f32,
+    level: f32,
+    samples_since_detection: usize,
+    blocks_since_detection: usize,
+    last_silence: Instant,
+}
+
+impl Detection {
+    fn new(consumer: Consumer<f32>, has_input: bool, settings: DetectionSettings) -> Self {
+        Self {
+            consumer,
+            has_input,
+            settings,
+            buf: VecDeque::with_capacity(SIZE + 1),
+            poly_detector: PolyDetector::new(SIZE),
+            analyser: Analyser::new(SIZE),
+            high_pass: Default::default(),
+            low_pass: Default::default(),
+            test_tone_phase: 0.0,
+            level: 0.0,
+            samples_since_detection: 0,
+            blocks_since_detection: 0,
+            last_silence: Instant::now(),
+        }
+    }
+
+    fn run(&mut self, settings: &Receiver<DetectionSettings>, blocks: &Sender<Block>) {
+        loop {
+            // only the newest settings matter
+            loop {
+                match settings.try_recv() {
+                    Ok(newer) => self.settings = newer,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            if let Some(cutoff) = self.settings.high_pass_cutoff {
+                self.high_pass.set_cutoff(cutoff, SAMPLE_RATE as f32);
+            }
+            if let Some(cutoff) = self.settings.low_pass_cutoff {
+                self.low_pass.set_cutoff(cutoff, SAMPLE_RATE as f32);
+            }
+
+            let mut missing_samples = 0;
+            if !self.has_input {
+                let elapsed = self.last_silence.elapsed().as_secs_f32();
+                missing_samples = (elapsed * SAMPLE_RATE as f32) as usize;
+                if missing_samples > 0 {
+                    self.last_silence = Instant::now();
+                }
+            }
+            if self.consumer.is_empty() && missing_samples == 0 {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            let level_decay = calc_level_decay(self.settings.level_decay_time);
+            while !self.consumer.is_empty() || missing_samples > 0 {
+                let sample = match self.consumer.pop() {
+                    Some(sample) => sample,
+                    None => {
+                        missing_samples -= 1;
+                        0.0
+                    }
+                };
+                if let Some(block) = self.process_sample(sample, level_decay) {
+                    if blocks.send(block).is_err() {
+                        // the main thread is gone
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a block every `hop_size` samples once the buffer is full.
+    fn process_sample(&mut self, mut recorded_sample: f32, level_decay: f32) -> Option<Block> {
+        let settings = self.settings;
+        if let Some(frequency) = settings.test_tone {
+            // the input still paces the loop, its samples are replaced by the tone
+            recorded_sample = 0.5 * (self.test_tone_phase * TAU).sin();
+            self.test_tone_phase = (self.test_tone_phase + frequency / SAMPLE_RATE as f32).fract();
+        }
+
+        // f32::max ignores NaN samples
+        self.level = recorded_sample.abs().max(self.level * level_decay);
+
+        let mut analysis_sample = recorded_sample;
+        if settings.high_pass_cutoff.is_some() {
+            analysis_sample = self.high_pass.process(analysis_sample);
+        }
+        if settings.low_pass_cutoff.is_some() {
+            analysis_sample = self.low_pass.process(analysis_sample);
+        }
+        self.buf.push_back(analysis_sample);
+        if self.buf.len() > SIZE {
+            self.buf.pop_front();
+        }
+        self.samples_since_detection += 1;
+
+        if self.buf.len() < SIZE || self.samples_since_detection < settings.hop_size {
+            return None;
+        }
+        self.samples_since_detection = 0;
+
+        // on slow machines only every Nth block is analysed, the others still advance the trail
+        self.blocks_since_detection += 1;
+        let keyboard_input = settings.keyboard_note.is_some();
+        let was_analysed =
+            keyboard_input || self.blocks_since_detection >= settings.detection_interval;
+        let pitches = if let Some(keyboard_note) = settings.keyboard_note {
+            // stands in for the detector, the rest of the pipeline can't tell the difference
+            self.blocks_since_detection = 0;
+            keyboard_note
+                .map(|midi| Pitch {
+                    frequency: midi_to_freq(midi as f32, settings.a4_reference),
+                    clarity: 1.0,
+                })
+                .into_iter()
+                .collect()
+        } else if was_analysed {
+            self.blocks_since_detection = 0;
+            self.detect()
+        } else {
+            Vec::new()
+        };
+
+        let spectrum = if settings.show_spectrogram {
+            Some(
+                self.analyser
+                    .analyse(self.buf.make_contiguous(), SAMPLE_RATE),
+            )
+        } else {
+            None
+        };
+
+        Some(Block {
+            was_analysed,
+            pitches,
+            level: self.level,
+            ring_buffer_fill: self.consumer.len() as f32 / self.consumer.capacity() as f32,
+            spectrum,
+        })
+    }
+
+    fn detect(&mut self) -> Vec<Pitch> {
+        let settings = self.settings;
+        // processing below only applies to this copy, the recorded samples stay untouched
+        let mut analysis_buf = self.buf.make_contiguous().to_vec();
+        if settings.soft_clip {
+            soft_clip(&mut analysis_buf);
+        }
+
+        match settings.polyphony_mode {
+            PolyphonyMode::Mono => {
+                // the polyphonic detector always uses its own Hann window
+                apply_window(&mut analysis_buf, settings.window_function);
+                detect_pitch(
+                    &analysis_buf,
+                    SAMPLE_RATE,
+                    settings.power_threshold,
+                    settings.clarity_threshold,
+                )
+                .into_iter()
+                .collect()
+            }
+            PolyphonyMode::Poly => self.poly_detector.detect(
+                &analysis_buf,
+                SAMPLE_RATE,
+                settings.power_threshold,
+                settings.max_voices,
+            ),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Buffered rows are written out at least this often (in seconds), so little
+/// is lost if the app crashes
+const FLUSH_INTERVAL: f64 = 1.0;
+
+/// Writes every detection to a CSV file as it happens. Unlike the note list,
+/// nothing is merged: each detection gets a row with its frequency after the
+/// sub-harmonic correction, and the note as displayed, after the dead zone and
+/// snapping.
+pub struct DetectionLog {
+    writer: BufWriter<File>,
+    last_flush: f64,
+    pub row_count: usize,
+}
+
+impl DetectionLog {
+    /// Creates (or truncates) `path` and writes a
+    /// `time,frequency,clarity,midi,tab,level` header.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "time,frequency,clarity,midi,tab,level")?;
+        Ok(Self {
+            writer,
+            last_flush: 0.0,
+            row_count: 0,
+        })
+    }
+
+    /// Adds a detection made `time` seconds after the app started.
+    pub fn log(
+        &mut self,
+        time: f64,
+        frequency: f32,
+        clarity: f32,
+        midi: u8,
+        tab: &str,
+        level: f32,
+    ) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{:.3},{:.2},{:.3},{},{},{:.4}",
+            time, frequency, clarity, midi, tab, level
+        )?;
+        self.row_count += 1;
+        if time - self.last_flush >= FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = time;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
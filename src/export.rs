@@ -0,0 +1,166 @@
+use nannou::color::LinSrgb;
+use nannou::prelude::{Vec2, Vec3};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builds a file name like `trail-1678900000.svg` in the current directory.
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("{}-{}.{}", prefix, timestamp, extension))
+}
+
+/// Writes the projected trail as one colored SVG line per segment, since a
+/// single polyline can't vary its stroke color.
+pub fn write_svg(path: &Path, points: &[(Vec2, LinSrgb)], weight: f32) -> io::Result<()> {
+    const MARGIN: f32 = 20.0;
+
+    let (min, max) = points.iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), (p, _)| (min.min(*p), max.max(*p)),
+    );
+    let size = (max - min).max(Vec2::ONE) + Vec2::splat(MARGIN * 2.0);
+
+    let mut svg = BufWriter::new(File::create(path)?);
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" style="background:black">"#,
+        size.x, size.y
+    )?;
+    // svg has y pointing down
+    let to_svg = |p: Vec2| (p.x - min.x + MARGIN, max.y - p.y + MARGIN);
+    for pair in points.windows(2) {
+        let (x1, y1) = to_svg(pair[0].0);
+        let (x2, y2) = to_svg(pair[1].0);
+        writeln!(
+            svg,
+            r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{}" stroke-width="{}" stroke-linecap="round"/>"#,
+            x1,
+            y1,
+            x2,
+            y2,
+            to_hex(pair[1].1),
+            weight
+        )?;
+    }
+    writeln!(svg, "</svg>")?;
+    svg.flush()
+}
+
+/// Writes the raw trail positions as an OBJ polyline, with the common
+/// `v x y z r g b` extension for vertex colors.
+pub fn write_obj(path: &Path, points: &[(Vec3, LinSrgb)]) -> io::Result<()> {
+    let mut obj = BufWriter::new(File::create(path)?);
+    for (p, c) in points {
+        writeln!(
+            obj,
+            "v {} {} {} {} {} {}",
+            p.x, p.y, p.z, c.red, c.green, c.blue
+        )?;
+    }
+    if points.len() > 1 {
+        write!(obj, "l")?;
+        // obj indices start at 1
+        for i in 1..=points.len() {
+            write!(obj, " {}", i)?;
+        }
+        writeln!(obj)?;
+    }
+    obj.flush()
+}
+
+fn to_hex(color: LinSrgb) -> String {
+    let encode = |c: f32| {
+        let c = c.clamp(0.0, 1.0);
+        let srgb = if c <= 0.003_130_8 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    };
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        encode(color.red),
+        encode(color.green),
+        encode(color.blue)
+    )
+}
+
+/// Tab written for rests.
+pub const REST: &str = "rest";
+
+/// A note held for `duration` seconds, starting `start` seconds into the take.
+/// Rests have no `midi` and `REST` as their tab.
+pub struct NoteEvent {
+    pub start: f32,
+    pub duration: f32,
+    pub midi: Option<u8>,
+    pub tab: String,
+}
+
+/// Folds rests shorter than `rest_threshold` seconds, and the repeated note that
+/// may follow them, into the note before.
+pub fn merge_short_rests(notes: Vec<NoteEvent>, rest_threshold: f32) -> Vec<NoteEvent> {
+    let mut merged: Vec<NoteEvent> = Vec::new();
+    for note in notes {
+        if let Some(previous) = merged.last_mut() {
+            let is_short_gap = note.midi.is_none() && note.duration < rest_threshold;
+            if is_short_gap || previous.midi == note.midi {
+                previous.duration = note.start + note.duration - previous.start;
+                continue;
+            }
+        }
+        merged.push(note);
+    }
+    merged
+}
+
+/// Writes notes as CSV with a `start,duration,midi,tab` header. Rests are
+/// written with an empty midi field.
+pub fn write_note_list(path: &Path, notes: &[NoteEvent]) -> io::Result<()> {
+    let mut csv = BufWriter::new(File::create(path)?);
+    writeln!(csv, "start,duration,midi,tab")?;
+    for note in notes {
+        let midi = note.midi.map_or(String::new(), |midi| midi.to_string());
+        writeln!(
+            csv,
+            "{:.3},{:.3},{},{}",
+            note.start, note.duration, midi, note.tab
+        )?;
+    }
+    csv.flush()
+}
+
+/// Reads a note list written by `write_note_list`.
+pub fn read_note_list(path: impl AsRef<Path>) -> Result<Vec<NoteEvent>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut notes = Vec::new();
+    // skip the header
+    for (line_number, line) in contents.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = || format!("line {}: expected start,duration,midi,tab", line_number + 1);
+        let mut fields = line.splitn(4, ',');
+        let mut next_number = || fields.next().and_then(|f| f.trim().parse::<f32>().ok());
+        let start = next_number().ok_or_else(error)?;
+        let duration = next_number().ok_or_else(error)?;
+        let midi = match fields.next().ok_or_else(error)?.trim() {
+            "" => None,
+            midi => Some(midi.parse::<f32>().map_err(|_| error())? as u8),
+        };
+        let tab = fields.next().unwrap_or("").to_owned();
+        notes.push(NoteEvent {
+            start,
+            duration,
+            midi,
+            tab,
+        });
+    }
+    Ok(notes)
+}
@@ -0,0 +1,43 @@
+use std::f32::consts::PI;
+
+/// One-pole low-pass filter. Keeps its state between calls so consecutive
+/// blocks are filtered without discontinuities.
+#[derive(Default)]
+pub struct LowPass {
+    coefficient: f32,
+    previous_output: f32,
+}
+
+impl LowPass {
+    pub fn set_cutoff(&mut self, cutoff: f32, sample_rate: f32) {
+        self.coefficient = 1.0 - (-2.0 * PI * cutoff / sample_rate).exp();
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.previous_output += self.coefficient * (sample - self.previous_output);
+        self.previous_output
+    }
+}
+
+/// One-pole high-pass filter, see `LowPass`.
+#[derive(Default)]
+pub struct HighPass {
+    coefficient: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl HighPass {
+    pub fn set_cutoff(&mut self, cutoff: f32, sample_rate: f32) {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        self.coefficient = rc / (rc + dt);
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.previous_output =
+            self.coefficient * (self.previous_output + sample - self.previous_input);
+        self.previous_input = sample;
+        self.previous_output
+    }
+}
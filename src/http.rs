@@ -0,0 +1,86 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two updates of the served detection
+const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Serves the latest detection as JSON on any HTTP GET, from a background thread.
+pub struct HttpServer {
+    latest: Arc<Mutex<String>>,
+    last_update: Instant,
+}
+
+impl HttpServer {
+    /// Only accepts connections from this machine unless `public` is set.
+    pub fn start(port: u16, public: bool) -> std::io::Result<Self> {
+        let address = if public { "0.0.0.0" } else { "127.0.0.1" };
+        let listener = TcpListener::bind((address, port))?;
+        let latest = Arc::new(Mutex::new("{}".to_owned()));
+        let served = Arc::clone(&latest);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream, &served) {
+                    println!("http: {}", e);
+                }
+            }
+        });
+        println!("serving detections on http://localhost:{}", port);
+        Ok(Self {
+            latest,
+            last_update: Instant::now(),
+        })
+    }
+
+    /// Replaces the served detection, at most every `UPDATE_INTERVAL`. Never
+    /// waits for the server thread, the update is skipped if it's busy.
+    pub fn publish(&mut self, note: &str, midi: u8, frequency: f32, clarity: f32, level: f32) {
+        self.replace(format!(
+            r#"{{"note":"{}","midi":{},"frequency":{:.2},"clarity":{:.3},"level":{:.3}}}"#,
+            escape(note),
+            midi,
+            frequency,
+            clarity,
+            level
+        ));
+    }
+
+    /// Like `publish`, for when nothing is being played. Everything but the level is null.
+    pub fn publish_silence(&mut self, level: f32) {
+        self.replace(format!(
+            r#"{{"note":null,"midi":null,"frequency":null,"clarity":null,"level":{:.3}}}"#,
+            level
+        ));
+    }
+
+    fn replace(&mut self, json: String) {
+        if self.last_update.elapsed() < UPDATE_INTERVAL {
+            return;
+        }
+        if let Ok(mut latest) = self.latest.try_lock() {
+            *latest = json;
+            self.last_update = Instant::now();
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, latest: &Mutex<String>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+    // the request itself doesn't matter, every path returns the detection
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+
+    let body = latest.lock().unwrap().clone();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
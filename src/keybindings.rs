@@ -0,0 +1,161 @@
+use crate::Action;
+use nannou::prelude::Key;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const KEYBINDINGS_PATH: &str = "keybindings.txt";
+
+/// The key bound to each bindable action, `None` if unbound.
+pub type KeyBindings = Vec<(Action, Option<Key>)>;
+
+/// Keys that can be bound, with the names used in the keybindings file.
+pub const BINDABLE_KEYS: [(Key, &str); 58] = [
+    (Key::F1, "F1"),
+    (Key::F2, "F2"),
+    (Key::F3, "F3"),
+    (Key::F4, "F4"),
+    (Key::F5, "F5"),
+    (Key::F6, "F6"),
+    (Key::F7, "F7"),
+    (Key::F8, "F8"),
+    (Key::F9, "F9"),
+    (Key::F10, "F10"),
+    (Key::F11, "F11"),
+    (Key::F12, "F12"),
+    (Key::A, "A"),
+    (Key::B, "B"),
+    (Key::C, "C"),
+    (Key::D, "D"),
+    (Key::E, "E"),
+    (Key::F, "F"),
+    (Key::G, "G"),
+    (Key::H, "H"),
+    (Key::I, "I"),
+    (Key::J, "J"),
+    (Key::K, "K"),
+    (Key::L, "L"),
+    (Key::M, "M"),
+    (Key::N, "N"),
+    (Key::O, "O"),
+    (Key::P, "P"),
+    (Key::Q, "Q"),
+    (Key::R, "R"),
+    (Key::S, "S"),
+    (Key::T, "T"),
+    (Key::U, "U"),
+    (Key::V, "V"),
+    (Key::W, "W"),
+    (Key::X, "X"),
+    (Key::Y, "Y"),
+    (Key::Z, "Z"),
+    (Key::Key0, "0"),
+    (Key::Key1, "1"),
+    (Key::Key2, "2"),
+    (Key::Key3, "3"),
+    (Key::Key4, "4"),
+    (Key::Key5, "5"),
+    (Key::Key6, "6"),
+    (Key::Key7, "7"),
+    (Key::Key8, "8"),
+    (Key::Key9, "9"),
+    (Key::Space, "Space"),
+    (Key::Return, "Return"),
+    (Key::Tab, "Tab"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::Home, "Home"),
+];
+
+pub fn key_name(key: Key) -> &'static str {
+    BINDABLE_KEYS
+        .iter()
+        .find(|(bindable, _)| *bindable == key)
+        .map_or("?", |(_, name)| name)
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    BINDABLE_KEYS
+        .iter()
+        .find(|(_, key_name)| key_name.eq_ignore_ascii_case(name))
+        .map(|(key, _)| *key)
+}
+
+/// Function keys only, so typing into text fields doesn't trigger anything.
+pub fn defaults() -> KeyBindings {
+    Action::BINDABLE
+        .iter()
+        .map(|&action| {
+            let key = match action {
+                Action::ToggleUi => Some(Key::F1),
+                Action::Reset => Some(Key::F2),
+                Action::TogglePause => Some(Key::F3),
+                Action::ToggleDetectionLog => Some(Key::F4),
+                Action::NextKey => Some(Key::F5),
+                Action::NextTuning => Some(Key::F6),
+                Action::ToggleTake => Some(Key::F7),
+                Action::ToggleFullscreen => Some(Key::F11),
+                Action::Screenshot => Some(Key::F12),
+                _ => None,
+            };
+            (action, key)
+        })
+        .collect()
+}
+
+pub fn action_for(bindings: &KeyBindings, key: Key) -> Option<Action> {
+    bindings
+        .iter()
+        .find(|(_, bound)| *bound == Some(key))
+        .map(|(action, _)| *action)
+}
+
+pub fn key_for(bindings: &KeyBindings, action: Action) -> Option<Key> {
+    bindings
+        .iter()
+        .find(|(bound, _)| *bound == action)
+        .and_then(|(_, key)| *key)
+}
+
+/// Reads bindings saved by `save`, one `<key> <action>` line per action, with
+/// `none` for unbound actions. Actions missing from the file, or on lines that
+/// can't be read, keep their default.
+pub fn load(path: impl AsRef<Path>) -> Option<KeyBindings> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut bindings = defaults();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        if load_line(&mut bindings, line).is_none() {
+            println!("ignoring keybinding \"{}\"", line);
+        }
+    }
+    Some(bindings)
+}
+
+fn load_line(bindings: &mut KeyBindings, line: &str) -> Option<()> {
+    let (key, action) = line.trim().split_once(' ')?;
+    let key = if key == "none" {
+        None
+    } else {
+        Some(parse_key(key)?)
+    };
+    let binding = bindings
+        .iter_mut()
+        .find(|(bindable, _)| bindable.name() == action.trim())?;
+    binding.1 = key;
+    Some(())
+}
+
+pub fn save(path: impl AsRef<Path>, bindings: &KeyBindings) -> io::Result<()> {
+    let contents: String = bindings
+        .iter()
+        .map(|(action, key)| {
+            let key = key.map_or("none", key_name);
+            format!("{} {}\n", key, action.name())
+        })
+        .collect();
+    fs::write(path, contents)
+}
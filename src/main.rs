@@ -1,27 +1,167 @@
-use nannou::color::{ConvertFrom, LinSrgb, Mix};
+use nannou::color::{ConvertFrom, LinSrgb, LinSrgba, Mix};
 use nannou::prelude::*;
 use nannou_audio as audio;
 use nannou_audio::Buffer;
 use nannou_egui::{self, egui, Egui};
-use ordered_float::NotNan;
 use pitch_detection::detector::mcleod::McLeodDetector;
 use pitch_detection::detector::PitchDetector;
-use ringbuf::{Consumer, Producer, RingBuffer};
+use ringbuf::{Producer, RingBuffer};
+use std::collections::VecDeque;
+use std::f32::consts::{PI, TAU};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+mod batch;
+mod detection;
+mod detection_log;
+mod export;
+mod filter;
+#[cfg(feature = "http")]
+mod http;
+mod keybindings;
+mod midi_out;
+mod monitor;
+mod osc;
+mod pitch_colors;
+mod poly;
+mod practice;
+mod spectrogram;
+mod themes;
+use detection::{DetectionSettings, DetectionThread};
+use detection_log::DetectionLog;
+use keybindings::KeyBindings;
+use midi_out::MidiOutput;
+use monitor::MonitorModel;
+use osc::OscOutput;
+use pitch_colors::PitchColors;
+use practice::Practice;
+use spectrogram::Spectrogram;
+use themes::Theme;
 
 const LINE_LENGTH: usize = 4096;
+const SAMPLE_RATE: usize = 44100;
+const SIZE: usize = 1024;
+const PADDING: usize = SIZE / 2;
+/// How far the trail head moves per second at a time scale of 1
+const Y_PER_SECOND: f32 = -0.1 * SAMPLE_RATE as f32 / SIZE as f32;
+const Z_PER_SECOND: f32 = 0.3 * SAMPLE_RATE as f32 / SIZE as f32;
+/// Longest time a single update advances the trail by, so a stall (e.g. a
+/// dragged window) doesn't leave a long jump
+const MAX_ADVANCE_TIME: f32 = 0.5;
+const DEFAULT_A4: f32 = 440.0;
+const A4_RANGE: RangeInclusive<f32> = 430.0..=450.0;
+/// The A4 reference set in the settings, one number in Hz
+const A4_REFERENCE_PATH: &str = "a4_reference.txt";
+const DEFAULT_POWER_THRESHOLD: f32 = 3.0;
+const DEFAULT_CLARITY_THRESHOLD: f32 = 0.7;
+/// Seconds of silence a note list needs before it writes a rest
+const DEFAULT_REST_THRESHOLD: f32 = 0.5;
+const KEYS: [&str; 16] = [
+    "C", "G", "D", "A", "E", "B", "F#", "Db", "Ab", "Eb", "Bb", "F", "LF", "LC", "LD", "HG",
+];
+const TUNINGS: [&str; 13] = [
+    "richter",
+    "country",
+    "wilde tuning",
+    "wilde minor tuning",
+    "melody maker",
+    "natural minor",
+    "harmonic minor",
+    "paddy richter",
+    "pentaharp",
+    "powerdraw",
+    "powerbender",
+    "diminished",
+    "easy 3rd",
+];
+/// Number of detections used as context for the sub-harmonic correction
+const RECENT_MIDI_LEN: usize = 8;
 
 struct Model {
-    locations: Vec<Vec3>,
-    camera_pos: Vec3,
-    _in_stream: audio::Stream<InputModel>,
-    consumer: Consumer<f32>,
+    locations: Vec<TrailPoint>,
+    camera: Camera,
+    saved_viewpoint: Option<Viewpoint>,
+    /// Live position of the trail head, before any lag compensation
+    head: TrailPoint,
+    /// Weaker pitches detected alongside the head in polyphonic mode
+    chord_points: VecDeque<TrailPoint>,
+    /// Recent head pitches (x, MIDI and clarity), used to delay the trail
+    delayed_pitches: VecDeque<(f32, f32, f32)>,
+    /// Actions requested from the UI, performed at the start of the next update
+    pending_actions: Vec<Action>,
+    /// Time since the last block arrived, the trail advances by it with the next block
+    unadvanced_time: f32,
+    session_active: bool,
+    session_quiet_time: f32,
+    /// `None` without an input device
+    _in_stream: Option<audio::Stream<InputModel>>,
+    /// Stereo output for the monitor tone, `None` if no output device is available
+    monitor_stream: Option<audio::Stream<MonitorModel>>,
+    /// Drains the input and detects pitches, results are picked up every update
+    detection: DetectionThread,
+    spectrogram: Spectrogram,
+    /// Note held on the computer keyboard in keyboard input mode
+    keyboard_midi: Option<u8>,
+    /// MIDI note played by the leftmost piano key
+    keyboard_base: u8,
+    /// Fraction of the ring buffer waiting to be processed when the last block was taken
+    ring_buffer_fill: f32,
     tuning_notes: Vec<String>,
+    themes: Vec<Theme>,
+    /// Tab for every MIDI note in the current key and tuning
+    tab_table: Vec<String>,
+    /// Detections per pitch class since the last reset
+    note_counts: [u32; 12],
+    current_midi: u8,
+    /// How far the detected pitch is from `current_midi`
+    current_cents: f32,
+    current_frequency: f32,
+    current_clarity: f32,
+    /// Power threshold used for detection, either manual or derived from the noise floor
+    power_threshold: f32,
+    /// Level of the input while nothing is detected
+    noise_floor: f32,
+    /// Recent detected MIDI values, before any correction
+    recent_midi: VecDeque<f32>,
+    /// Last note committed to the display, kept while the pitch stays within the dead zone
+    displayed_midi: Option<u8>,
     current_note: String,
     current_level: f32,
     ui_visible: bool,
     egui: Egui,
     settings: Settings,
     is_running: bool,
+    seconds_since_detection: f32,
+    /// Stops the trail from advancing until unpaused
+    paused: bool,
+    has_pitch: bool,
+    /// Number of points at the start of `locations` recorded before the first detection
+    leading_silent_points: usize,
+    clear_alpha: f32,
+    /// Clear the whole screen on the next frame instead of fading, e.g. after a reset
+    clear_screen: bool,
+    applied_fps_cap: Option<f64>,
+    practice: Option<Practice>,
+    practice_status: String,
+    detection_log: Option<DetectionLog>,
+    detection_log_status: String,
+    /// Note list path of the take being recorded, its detection log is saved next to it
+    take_path: Option<PathBuf>,
+    /// Points of the current take or auto session that no longer fit in `locations`,
+    /// oldest first
+    scrolled_off_points: Vec<TrailPoint>,
+    /// Reference take drawn behind the live trail
+    ghost: Vec<export::NoteEvent>,
+    ghost_status: String,
+    /// Frozen trails for comparing with the live one, oldest first
+    snapshots: Vec<Snapshot>,
+    osc_output: Option<OscOutput>,
+    #[cfg(feature = "http")]
+    http_server: Option<http::HttpServer>,
+    osc_status: String,
+    midi_output: Option<MidiOutput>,
+    midi_ports: Vec<String>,
+    midi_status: String,
 
     line_bounds: [f32; 2],
     midi_bounds: MidiBounds,
@@ -30,11 +170,444 @@ struct Model {
 struct Settings {
     power_threshold: f32,
     clarity_threshold: f32,
+    /// Detections below this clarity don't move the trail
+    trail_clarity_threshold: f32,
+    /// Whether detections below `trail_clarity_threshold` still update the note readout
+    unclear_updates_note: bool,
+    /// Frequency of A4 in Hz that notes are measured against
+    a4_reference: f32,
     key: &'static str,
     tuning: &'static str,
+    notation: NotationStyle,
     left_color: LinSrgb,
+    center_color: LinSrgb,
     right_color: LinSrgb,
+    background_color: LinSrgb,
+    note_color: LinSrgb,
+    theme: String,
     should_calc_bounds_from_key: bool,
+    fade_time: f32,
+    /// Stretches (above 1) or compresses the time axis of the trail
+    time_scale: f32,
+    melody_path: String,
+    /// A new `detections-<timestamp>.csv` is used for every log if empty
+    detection_log_path: String,
+    ghost_path: String,
+    show_ghost: bool,
+    /// Caption for the next snapshot
+    snapshot_label: String,
+    show_snapshots: bool,
+    level_decay_time: f32,
+    depth_shading: bool,
+    line_style: LineStyle,
+    /// Desaturate points by how unclear their detection was
+    clarity_shading: bool,
+    clarity_shading_strength: f32,
+    /// Draw low notes on the right, the left color then belongs to the right side
+    mirror_x: bool,
+    /// Draw the trail growing upwards instead of downwards
+    invert_y: bool,
+    /// Move the head away from the viewer, older points come closer
+    invert_z: bool,
+    start_running: bool,
+    auto_key_with_tuning: bool,
+    show_legend: bool,
+    color_mode: ColorMode,
+    x_axis_scale: XAxisScale,
+    pitch_colors: PitchColors,
+    osc_enabled: bool,
+    osc_host: String,
+    osc_port: u16,
+    midi_port: Option<String>,
+    hop_size: usize,
+    polyphony_mode: PolyphonyMode,
+    /// Most pitches reported per block in polyphonic mode
+    max_voices: usize,
+    visualization_mode: VisualizationMode,
+    show_histogram: bool,
+    /// Spectrum of recent blocks with the detected pitch marked
+    show_spectrogram: bool,
+    /// Animate a hint while nothing has been detected for a while
+    idle_animation: bool,
+    soft_clip: bool,
+    window_function: WindowFunction,
+    /// Render rate limit, uncapped if `None`
+    fps_cap: Option<f64>,
+    keep_camera_on_reset: bool,
+    /// Only run detection on every Nth block
+    detection_interval: usize,
+    snap_to_tuning: bool,
+    /// Cutoff in Hz, off if `None`
+    high_pass_cutoff: Option<f32>,
+    /// Cutoff in Hz, off if `None`
+    low_pass_cutoff: Option<f32>,
+    /// Frequency of a sine fed to the detector instead of the input, off if `None`
+    test_tone: Option<f32>,
+    /// Notes come from the computer keyboard instead of the detector
+    keyboard_input: bool,
+    note_font_size: u32,
+    note_anchor: NoteAnchor,
+    /// Shifts the trail pitch later (positive) or earlier (negative) relative to detection
+    lag_compensation_ms: f32,
+    subharmonic_correction: bool,
+    auto_threshold: bool,
+    /// Factor between the noise floor level and the level needed for detection
+    threshold_margin: f32,
+    /// How quickly the noise floor follows the input level, per analysed block
+    threshold_adapt_rate: f32,
+    monitor_enabled: bool,
+    /// Pan the monitor tone by the note's x position
+    monitor_pan: bool,
+    /// How far past the midpoint between two notes the pitch has to move to change the tab
+    dead_zone_cents: f32,
+    /// Seconds between gridlines across the trail, off if `None`
+    grid_interval: Option<f32>,
+    auto_session: bool,
+    session_start_level: f32,
+    session_stop_level: f32,
+    /// Seconds the level has to stay below the stop level to end a take
+    session_hangover: f32,
+    session_export: Option<ExportFormat>,
+    /// Seconds without a detection that are exported as a rest instead of extending the note
+    rest_threshold: f32,
+    keybindings: KeyBindings,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VisualizationMode {
+    Spiral,
+    Circular,
+    Tuner,
+}
+
+impl VisualizationMode {
+    const ALL: [VisualizationMode; 3] = [
+        VisualizationMode::Spiral,
+        VisualizationMode::Circular,
+        VisualizationMode::Tuner,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            VisualizationMode::Spiral => "spiral",
+            VisualizationMode::Circular => "circular",
+            VisualizationMode::Tuner => "tuner",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PolyphonyMode {
+    /// Single pitch from the McLeod detector
+    Mono,
+    /// Several pitches from peaks in the spectrum, for chords
+    Poly,
+}
+
+impl PolyphonyMode {
+    const ALL: [PolyphonyMode; 2] = [PolyphonyMode::Mono, PolyphonyMode::Poly];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PolyphonyMode::Mono => "mono",
+            PolyphonyMode::Poly => "polyphonic",
+        }
+    }
+}
+
+/// Follows the head of the trail, with a user adjustable view on top.
+#[derive(Default)]
+struct Camera {
+    pos: Vec3,
+    viewpoint: Viewpoint,
+}
+
+#[derive(Clone, Copy)]
+struct Viewpoint {
+    offset: Vec2,
+    zoom: f32,
+}
+
+impl Default for Viewpoint {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+/// Where the current note is drawn
+#[derive(Clone, Copy, PartialEq)]
+enum NoteAnchor {
+    FollowPoint,
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl NoteAnchor {
+    const ALL: [NoteAnchor; 6] = [
+        NoteAnchor::FollowPoint,
+        NoteAnchor::Center,
+        NoteAnchor::TopLeft,
+        NoteAnchor::TopRight,
+        NoteAnchor::BottomLeft,
+        NoteAnchor::BottomRight,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            NoteAnchor::FollowPoint => "follow last point",
+            NoteAnchor::Center => "center",
+            NoteAnchor::TopLeft => "top left",
+            NoteAnchor::TopRight => "top right",
+            NoteAnchor::BottomLeft => "bottom left",
+            NoteAnchor::BottomRight => "bottom right",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Reset,
+    Export(ExportFormat),
+    ToggleUi,
+    TogglePause,
+    Screenshot,
+    ToggleFullscreen,
+    NextKey,
+    NextTuning,
+    CaptureSnapshot,
+    ToggleDetectionLog,
+    ToggleTake,
+}
+
+impl Action {
+    /// Actions that can be bound to a key
+    const BINDABLE: [Action; 13] = [
+        Action::ToggleUi,
+        Action::Reset,
+        Action::TogglePause,
+        Action::Screenshot,
+        Action::ToggleFullscreen,
+        Action::NextKey,
+        Action::NextTuning,
+        Action::CaptureSnapshot,
+        Action::ToggleDetectionLog,
+        Action::ToggleTake,
+        Action::Export(ExportFormat::Svg),
+        Action::Export(ExportFormat::Obj),
+        Action::Export(ExportFormat::NoteList),
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Reset => "reset",
+            Action::Export(ExportFormat::Svg) => "export SVG",
+            Action::Export(ExportFormat::Obj) => "export OBJ",
+            Action::Export(ExportFormat::NoteList) => "export note list",
+            Action::ToggleUi => "toggle UI",
+            Action::TogglePause => "pause",
+            Action::Screenshot => "screenshot",
+            Action::ToggleFullscreen => "fullscreen",
+            Action::NextKey => "next key",
+            Action::NextTuning => "next tuning",
+            Action::CaptureSnapshot => "capture snapshot",
+            Action::ToggleDetectionLog => "detection log",
+            Action::ToggleTake => "record take",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Svg,
+    Obj,
+    NoteList,
+}
+
+impl ExportFormat {
+    const ALL: [ExportFormat; 3] = [ExportFormat::Svg, ExportFormat::Obj, ExportFormat::NoteList];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Svg => "SVG",
+            ExportFormat::Obj => "OBJ",
+            ExportFormat::NoteList => "note list",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorMode {
+    /// Mix left/right colors by x position within `line_bounds`
+    Position,
+    /// Mix left/right colors over a fixed MIDI range
+    AbsolutePitch,
+    /// Look up the color of the point's pitch class
+    PitchClass,
+}
+
+impl ColorMode {
+    const ALL: [ColorMode; 3] = [
+        ColorMode::Position,
+        ColorMode::AbsolutePitch,
+        ColorMode::PitchClass,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Position => "position",
+            ColorMode::AbsolutePitch => "absolute pitch",
+            ColorMode::PitchClass => "pitch class",
+        }
+    }
+}
+
+/// Weighting applied to the analysis block before monophonic detection.
+#[derive(Clone, Copy, PartialEq)]
+enum WindowFunction {
+    /// Unweighted, every sample counts fully
+    Rectangular,
+    /// Tapers to zero at the edges
+    Hann,
+    /// Like Hann, but keeps a little of the edges
+    Hamming,
+}
+
+impl WindowFunction {
+    const ALL: [WindowFunction; 3] = [
+        WindowFunction::Rectangular,
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            WindowFunction::Rectangular => "rectangular",
+            WindowFunction::Hann => "Hann",
+            WindowFunction::Hamming => "Hamming",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LineStyle {
+    /// Smooth joins for thick lines
+    Mesh,
+    /// nannou's polyline, cheaper but shows gaps at sharp bends
+    Polyline,
+}
+
+impl LineStyle {
+    const ALL: [LineStyle; 2] = [LineStyle::Mesh, LineStyle::Polyline];
+
+    fn name(&self) -> &'static str {
+        match self {
+            LineStyle::Mesh => "mesh",
+            LineStyle::Polyline => "polyline",
+        }
+    }
+}
+
+/// How tabs are written. Every style is a rewrite of harptabber's notation
+/// (`4` blow, `-4` draw, `'` per semitone of bend, `o` for overblows and overdraws).
+#[derive(Clone, Copy, PartialEq)]
+enum NotationStyle {
+    /// harptabber's own output
+    Native,
+    /// `+4` for blow notes
+    PlusBlow,
+    /// `b` instead of `'` for bends, e.g. `-3bb`
+    LetterBends,
+}
+
+impl NotationStyle {
+    const ALL: [NotationStyle; 3] = [
+        NotationStyle::Native,
+        NotationStyle::PlusBlow,
+        NotationStyle::LetterBends,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            NotationStyle::Native => "harptabber (4, -4, -3')",
+            NotationStyle::PlusBlow => "explicit blow (+4, -4, -3')",
+            NotationStyle::LetterBends => "letter bends (4, -4, -3b)",
+        }
+    }
+
+    /// Name used with `--notation`
+    fn arg_name(&self) -> &'static str {
+        match self {
+            NotationStyle::Native => "native",
+            NotationStyle::PlusBlow => "plus-blow",
+            NotationStyle::LetterBends => "letter-bends",
+        }
+    }
+
+    /// Rewrites `tab` in this style. `tab` may be in any of the styles.
+    fn format(&self, tab: &str) -> String {
+        let native = native_tab(tab);
+        match self {
+            NotationStyle::Native => native,
+            NotationStyle::PlusBlow if native.is_empty() || native.starts_with('-') => native,
+            NotationStyle::PlusBlow => format!("+{}", native),
+            NotationStyle::LetterBends => native.replace('\'', "b"),
+        }
+    }
+}
+
+/// Converts a tab written in any `NotationStyle` back to harptabber's notation.
+fn native_tab(tab: &str) -> String {
+    tab.trim_start_matches('+').replace('b', "'")
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum XAxisScale {
+    /// Equal spacing per semitone
+    Midi,
+    /// Equal spacing per Hz, higher notes spread further apart
+    LinearHz,
+}
+
+impl XAxisScale {
+    const ALL: [XAxisScale; 2] = [XAxisScale::Midi, XAxisScale::LinearHz];
+
+    fn name(&self) -> &'static str {
+        match self {
+            XAxisScale::Midi => "MIDI (chromatic)",
+            XAxisScale::LinearHz => "linear Hz",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TrailPoint {
+    pos: Vec3,
+    midi: f32,
+    /// Clarity of the detection the point was recorded with
+    clarity: f32,
+    /// Whether a pitch was detected for this point, silent points keep the last pitch
+    is_pitched: bool,
+    /// Seconds the trail had advanced when the point was recorded, unlike `pos` this
+    /// doesn't depend on the time scale
+    time: f32,
+}
+
+impl Default for TrailPoint {
+    fn default() -> Self {
+        Self {
+            pos: Vec3::ZERO,
+            midi: 60.0,
+            clarity: 1.0,
+            is_pitched: false,
+            time: 0.0,
+        }
+    }
 }
 
 struct MidiBounds {
@@ -49,7 +622,121 @@ impl Default for MidiBounds {
 }
 
 fn main() {
-    nannou::app(model).update(update).run();
+    if let Some(dir) = arg_value("--batch") {
+        // offline processing, no window or audio devices needed
+        if let Some(options) = batch_options() {
+            batch::run(Path::new(&dir), &options);
+        }
+        return;
+    }
+    nannou::app(model).update(update).exit(exit).run();
+}
+
+fn exit(_app: &App, mut model: Model) {
+    if model.take_path.is_some() {
+        toggle_take(&mut model);
+    }
+    if model.detection_log.is_some() {
+        toggle_detection_log(&mut model);
+    }
+    // dropping the model stops the detection thread
+}
+
+/// Batch settings from `--key`, `--tuning`, `--notation` and `--a4`, with the live
+/// defaults otherwise.
+fn batch_options() -> Option<batch::BatchOptions> {
+    let key = arg_value("--key").unwrap_or_else(|| "C".to_owned());
+    if !KEYS.contains(&key.as_str()) {
+        println!("unknown key {}, expected one of {}", key, KEYS.join(", "));
+        return None;
+    }
+    let tuning = arg_value("--tuning").unwrap_or_else(|| "richter".to_owned());
+    if !TUNINGS.contains(&tuning.as_str()) {
+        println!(
+            "unknown tuning {}, expected one of {}",
+            tuning,
+            TUNINGS.join(", ")
+        );
+        return None;
+    }
+    let notation_name = arg_value("--notation").unwrap_or_else(|| "native".to_owned());
+    let notation = match NotationStyle::ALL
+        .into_iter()
+        .find(|notation| notation.arg_name() == notation_name)
+    {
+        Some(notation) => notation,
+        None => {
+            let names: Vec<&str> = NotationStyle::ALL.iter().map(|n| n.arg_name()).collect();
+            println!(
+                "unknown notation {}, expected one of {}",
+                notation_name,
+                names.join(", ")
+            );
+            return None;
+        }
+    };
+    let tuning_notes = harptabber::tuning_to_notes_in_order(&tuning).0;
+    Some(batch::BatchOptions {
+        tab_table: build_tab_table(&key, &tuning_notes, notation),
+        a4_reference: initial_a4_reference(),
+        power_threshold: DEFAULT_POWER_THRESHOLD,
+        clarity_threshold: DEFAULT_CLARITY_THRESHOLD,
+        rest_threshold: DEFAULT_REST_THRESHOLD,
+    })
+}
+
+/// `--a4` if given, otherwise the reference last set in the settings, kept in `A4_RANGE`.
+fn initial_a4_reference() -> f32 {
+    let a4: f32 = arg_value("--a4")
+        .and_then(|value| {
+            value
+                .parse()
+                .map_err(|_| println!("invalid A4 {}", value))
+                .ok()
+        })
+        .or_else(|| {
+            std::fs::read_to_string(A4_REFERENCE_PATH)
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .unwrap_or(DEFAULT_A4);
+    if A4_RANGE.contains(&a4) {
+        return a4;
+    }
+    let clamped = if a4.is_nan() {
+        DEFAULT_A4
+    } else {
+        a4.clamp(*A4_RANGE.start(), *A4_RANGE.end())
+    };
+    println!(
+        "A4 has to be between {} and {} Hz, using {}",
+        A4_RANGE.start(),
+        A4_RANGE.end(),
+        clamped
+    );
+    clamped
+}
+
+/// Value following `name` on the command line, e.g. `--http-port 8080`
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|arg| arg == name)?;
+    args.next()
+}
+
+/// Positive integer following `name` on the command line, `default` if it's
+/// missing or invalid.
+fn positive_arg(name: &str, default: u32) -> u32 {
+    match arg_value(name).map(|value| value.parse::<u32>()) {
+        Some(Ok(value)) if value > 0 => value,
+        Some(_) => {
+            println!("{} has to be a positive number, using {}", name, default);
+            default
+        }
+        None => default,
+    }
 }
 
 fn model(app: &App) -> Model {
@@ -58,10 +745,46 @@ fn model(app: &App) -> Model {
         .view(view)
         .raw_event(raw_window_event)
         .key_pressed(key_pressed)
-        .size(1920, 1080)
+        .key_released(key_released)
+        .size(
+            positive_arg("--width", 1920),
+            positive_arg("--height", 1080),
+        )
+        .title(arg_value("--title").unwrap_or_else(|| "tab-visualizer".to_owned()))
         .build()
         .unwrap();
 
+    if let Some(index) = arg_value("--monitor") {
+        let monitors = app.available_monitors();
+        match index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| monitors.get(index))
+        {
+            Some(monitor) => app
+                .window(window_id)
+                .unwrap()
+                .winit_window()
+                .set_outer_position(monitor.position()),
+            None => println!(
+                "invalid monitor {}, expected 0 to {}",
+                index,
+                monitors.len().saturating_sub(1)
+            ),
+        }
+    }
+
+    let tuning_notes = harptabber::tuning_to_notes_in_order("richter").0;
+    let tab_table = build_tab_table("C", &tuning_notes, NotationStyle::Native);
+
+    let mut themes = themes::builtin_themes();
+    if Path::new(themes::THEMES_PATH).exists() {
+        match themes::load(themes::THEMES_PATH) {
+            Ok(loaded) => themes.extend(loaded),
+            Err(e) => println!("failed to load {}: {}", themes::THEMES_PATH, e),
+        }
+    }
+
     let window = app.window(window_id).unwrap();
     let egui = Egui::from_window(&window);
 
@@ -84,101 +807,472 @@ fn model(app: &App) -> Model {
         .new_input_stream(in_model)
         .capture(pass_in)
         .build()
-        .unwrap();
+        .map_err(|e| println!("no audio input available, only keyboard input works: {}", e))
+        .ok();
+    if let Some(in_stream) = &in_stream {
+        in_stream.play().unwrap();
+    }
+    let detection = DetectionThread::spawn(cons, in_stream.is_some());
+
+    // starts paused, it's only played while monitoring is enabled
+    let monitor_stream = audio_host
+        .new_output_stream(MonitorModel::default())
+        .render(monitor::render)
+        .channels(2)
+        .build()
+        .map_err(|e| println!("no monitor output available: {}", e))
+        .ok();
 
-    in_stream.play().unwrap();
+    let start_running = std::env::args().any(|arg| arg == "--start-running");
+    let a4_reference = initial_a4_reference();
 
     Model {
         locations: Vec::with_capacity(LINE_LENGTH),
-        camera_pos: Vec3::ZERO,
+        camera: Camera::default(),
+        saved_viewpoint: None,
+        head: TrailPoint::default(),
+        chord_points: VecDeque::new(),
+        delayed_pitches: VecDeque::new(),
+        pending_actions: Vec::new(),
+        unadvanced_time: 0.0,
+        session_active: false,
+        session_quiet_time: 0.0,
         _in_stream: in_stream,
-        consumer: cons,
-        tuning_notes: harptabber::tuning_to_notes_in_order("richter").0,
+        monitor_stream,
+        detection,
+        spectrogram: Spectrogram::new(SIZE, SAMPLE_RATE),
+        keyboard_midi: None,
+        keyboard_base: 60,
+        ring_buffer_fill: 0.0,
+        tuning_notes,
+        themes,
+        tab_table,
+        note_counts: [0; 12],
+        current_midi: 60,
+        current_cents: 0.0,
+        current_frequency: 0.0,
+        current_clarity: 0.0,
+        power_threshold: DEFAULT_POWER_THRESHOLD,
+        noise_floor: 0.0,
+        recent_midi: VecDeque::with_capacity(RECENT_MIDI_LEN + 1),
+        displayed_midi: None,
         current_note: "4".to_owned(),
         current_level: 0.0,
         ui_visible: true,
         egui,
-        is_running: false,
+        is_running: start_running,
+        seconds_since_detection: 0.0,
+        paused: false,
+        has_pitch: false,
+        leading_silent_points: 0,
+        clear_alpha: 0.15,
+        clear_screen: false,
+        applied_fps_cap: None,
+        practice: None,
+        practice_status: String::new(),
+        detection_log: None,
+        detection_log_status: String::new(),
+        take_path: None,
+        scrolled_off_points: Vec::new(),
+        ghost: Vec::new(),
+        ghost_status: String::new(),
+        snapshots: Vec::new(),
+        osc_output: None,
+        #[cfg(feature = "http")]
+        http_server: arg_value("--http-port").and_then(|port| {
+            let port = port
+                .parse()
+                .map_err(|_| println!("invalid port {}", port))
+                .ok()?;
+            let public = std::env::args().any(|arg| arg == "--http-public");
+            http::HttpServer::start(port, public)
+                .map_err(|e| println!("failed to start http server: {}", e))
+                .ok()
+        }),
+        osc_status: String::new(),
+        midi_output: None,
+        midi_ports: midi_out::list_ports(),
+        midi_status: String::new(),
         line_bounds: [-8.0, 8.0],
         midi_bounds: calc_freq_bounds("C"),
         settings: Settings {
-            power_threshold: 3.0,
-            clarity_threshold: 0.7,
+            power_threshold: DEFAULT_POWER_THRESHOLD,
+            clarity_threshold: DEFAULT_CLARITY_THRESHOLD,
+            trail_clarity_threshold: 0.0,
+            unclear_updates_note: true,
             key: "C",
             tuning: "richter",
+            notation: NotationStyle::Native,
             left_color: lin_srgb(0.0, 0.1, 0.8),
+            center_color: lin_srgb(0.5, 0.1, 0.8),
             right_color: lin_srgb(1.0, 0.1, 0.8),
+            background_color: lin_srgb(0.0, 0.0, 0.0),
+            note_color: lin_srgb(1.0, 1.0, 1.0),
+            theme: "dark".to_owned(),
             should_calc_bounds_from_key: true,
+            fade_time: 0.5,
+            time_scale: 1.0,
+            melody_path: String::new(),
+            detection_log_path: String::new(),
+            ghost_path: String::new(),
+            show_ghost: true,
+            snapshot_label: String::new(),
+            show_snapshots: true,
+            level_decay_time: 0.3,
+            depth_shading: false,
+            line_style: LineStyle::Mesh,
+            clarity_shading: false,
+            clarity_shading_strength: 0.8,
+            mirror_x: false,
+            invert_y: false,
+            invert_z: false,
+            start_running,
+            auto_key_with_tuning: false,
+            show_legend: false,
+            color_mode: ColorMode::Position,
+            x_axis_scale: XAxisScale::Midi,
+            pitch_colors: pitch_colors::load(pitch_colors::PITCH_COLORS_PATH)
+                .unwrap_or_else(pitch_colors::default_rainbow),
+            osc_enabled: false,
+            osc_host: "127.0.0.1".to_owned(),
+            osc_port: 9000,
+            midi_port: None,
+            a4_reference,
+            hop_size: SIZE,
+            polyphony_mode: PolyphonyMode::Mono,
+            max_voices: 3,
+            visualization_mode: VisualizationMode::Spiral,
+            show_histogram: false,
+            show_spectrogram: false,
+            idle_animation: true,
+            soft_clip: false,
+            window_function: WindowFunction::Rectangular,
+            fps_cap: None,
+            keep_camera_on_reset: false,
+            detection_interval: 1,
+            snap_to_tuning: false,
+            high_pass_cutoff: None,
+            low_pass_cutoff: None,
+            test_tone: None,
+            keyboard_input: false,
+            note_font_size: 32,
+            note_anchor: NoteAnchor::FollowPoint,
+            lag_compensation_ms: 0.0,
+            subharmonic_correction: false,
+            auto_threshold: false,
+            threshold_margin: 3.0,
+            threshold_adapt_rate: 0.05,
+            monitor_enabled: false,
+            monitor_pan: false,
+            dead_zone_cents: 0.0,
+            grid_interval: None,
+            auto_session: false,
+            session_start_level: 0.2,
+            session_stop_level: 0.05,
+            session_hangover: 2.0,
+            session_export: None,
+            rest_threshold: DEFAULT_REST_THRESHOLD,
+            keybindings: keybindings::load(keybindings::KEYBINDINGS_PATH)
+                .unwrap_or_else(keybindings::defaults),
         },
     }
 }
 
-fn update(_app: &App, model: &mut Model, update: Update) {
+fn update(app: &App, model: &mut Model, update: Update) {
     ui(model, update);
-    let settings = &mut model.settings;
+    model.clear_screen = false;
+    for action in std::mem::take(&mut model.pending_actions) {
+        perform_action(app, model, action);
+    }
+    let settings = &model.settings;
 
-    let mut new_pos = if let Some(pos) = model.locations.last() {
-        *pos
-    } else {
-        Vec3::ZERO
-    };
+    if settings.fps_cap != model.applied_fps_cap {
+        // the detection thread drains the input on its own, so no audio is lost at lower rates
+        match settings.fps_cap {
+            Some(fps) => app.set_loop_mode(LoopMode::rate_fps(fps)),
+            None => app.set_loop_mode(LoopMode::RefreshSync),
+        }
+        model.applied_fps_cap = settings.fps_cap;
+    }
 
-    let mut buf = Vec::with_capacity(1024);
-    while !model.consumer.is_empty() {
-        let recorded_sample = model.consumer.pop().unwrap_or(0.0);
+    model.clear_alpha = calc_clear_alpha(update.since_last.as_secs_f32(), settings.fade_time);
+    model.seconds_since_detection += update.since_last.as_secs_f32();
 
-        buf.push(recorded_sample);
-        if buf.len() == 1024 {
-            model.current_level = buf
-                .iter()
-                .filter_map(|x| NotNan::new(x.abs()).ok())
-                .max()
-                .unwrap()
-                .into();
-
-            const SAMPLE_RATE: usize = 44100;
-            const SIZE: usize = 1024;
-            const PADDING: usize = SIZE / 2;
-
-            let mut detector = McLeodDetector::new(SIZE, PADDING);
-
-            if let Some(pitch) = detector.get_pitch(
-                &buf,
-                SAMPLE_RATE,
-                settings.power_threshold,
-                settings.clarity_threshold,
-            ) {
-                model.is_running = true;
-                println!("pitch: {}, clarity: {}", pitch.frequency, pitch.clarity);
-                let frequency = pitch.frequency;
-                let midi = freq_to_midi(frequency);
-                new_pos.x = map_range(
-                    freq_to_midi_float(frequency),
-                    model.midi_bounds.low as f32,
-                    model.midi_bounds.high as f32,
-                    model.line_bounds[0],
-                    model.line_bounds[1],
+    let mut new_point = model.head;
+    let lag_blocks = (settings.lag_compensation_ms / 1000.0 * SAMPLE_RATE as f32
+        / settings.hop_size as f32)
+        .round() as isize;
+
+    model.detection.send_settings(detection_settings(model));
+
+    // collected first, integrating a block needs the whole model
+    let blocks: Vec<detection::Block> = model.detection.blocks.try_iter().collect();
+    // the trail follows wall-clock time, shared between the blocks that arrived since
+    model.unadvanced_time =
+        (model.unadvanced_time + update.since_last.as_secs_f32()).min(MAX_ADVANCE_TIME);
+    let mut block_time = 0.0;
+    if !blocks.is_empty() {
+        block_time = model.unadvanced_time / blocks.len() as f32;
+        model.unadvanced_time = 0.0;
+    }
+    for block in blocks {
+        model.current_level = block.level;
+        model.ring_buffer_fill = block.ring_buffer_fill;
+        let should_detect = block.was_analysed;
+        let pitches = block.pitches;
+        // the strongest pitch drives the trail head, tab display and outputs
+        let pitch = pitches.first().copied();
+
+        if settings.auto_threshold {
+            if should_detect && pitch.is_none() {
+                // only silence (or noise) contributes to the noise floor
+                model.noise_floor +=
+                    settings.threshold_adapt_rate * (model.current_level - model.noise_floor);
+            }
+            model.power_threshold = level_to_power(model.noise_floor * settings.threshold_margin);
+        } else {
+            model.power_threshold = settings.power_threshold;
+        }
+
+        let is_clear_enough =
+            |pitch: &poly::Pitch| pitch.clarity >= settings.trail_clarity_threshold;
+        let pitch = pitch.filter(|pitch| settings.unclear_updates_note || is_clear_enough(pitch));
+
+        if let Some(levels) = block.spectrum {
+            model.spectrogram.push(spectrogram::Column {
+                levels,
+                fundamental: pitch.map(|pitch| pitch.frequency),
+            });
+        }
+
+        let mut detected_midi = None;
+        if let Some(pitch) = pitch {
+            let previous_point = new_point;
+            let was_idle = !model.has_pitch || model.seconds_since_detection > IDLE_DELAY;
+            if settings.idle_animation && was_idle {
+                // don't let the idle hint fade out behind the trail
+                model.clear_screen = true;
+            }
+            model.is_running = true;
+            model.seconds_since_detection = 0.0;
+            model.has_pitch = true;
+            println!("pitch: {}, clarity: {}", pitch.frequency, pitch.clarity);
+            let mut frequency = pitch.frequency;
+            if settings.subharmonic_correction {
+                let raw_midi = freq_to_midi_float(frequency, settings.a4_reference);
+                if is_octave_above(raw_midi, &model.recent_midi) {
+                    frequency /= 2.0;
+                }
+                // keep the raw value, so the average follows a real octave jump after a while
+                model.recent_midi.push_back(raw_midi);
+                if model.recent_midi.len() > RECENT_MIDI_LEN {
+                    model.recent_midi.pop_front();
+                }
+            }
+            new_point.midi = freq_to_midi_float(frequency, settings.a4_reference);
+            let mut midi = apply_dead_zone(
+                new_point.midi,
+                model.displayed_midi,
+                settings.dead_zone_cents,
+            );
+            model.displayed_midi = Some(midi);
+            let unsnapped_midi = new_point.midi;
+            model.current_frequency = frequency;
+            model.current_clarity = pitch.clarity;
+            new_point.clarity = pitch.clarity;
+            if settings.snap_to_tuning {
+                if let Some(snapped) = nearest_playable_note(new_point.midi, &model.tab_table) {
+                    midi = snapped;
+                    new_point.midi = snapped as f32;
+                }
+            }
+            // measured against the note that's shown, which may be far off after snapping
+            model.current_cents = (unsnapped_midi - midi as f32) * 100.0;
+            detected_midi = Some(midi);
+            model.note_counts[midi as usize % 12] += 1;
+            model.current_midi = midi;
+            new_point.pos.x = midi_to_x(new_point.midi, model);
+            model.current_note = model.tab_table[midi as usize].clone();
+            if let Some(osc_output) = &model.osc_output {
+                osc_output.send_detection(frequency, midi, pitch.clarity, &model.current_note);
+            }
+            if let Some(log) = &mut model.detection_log {
+                let result = log.log(
+                    update.since_start.as_secs_f64(),
+                    frequency,
+                    pitch.clarity,
+                    midi,
+                    &model.current_note,
+                    model.current_level,
                 );
-                model.current_note = midi_to_tab(midi, settings.key, &model.tuning_notes);
+                if let Err(e) = result {
+                    model.detection_log = None;
+                    model.detection_log_status = format!("detection log stopped: {}", e);
+                    println!("{}", model.detection_log_status);
+                }
+            }
+            if !is_clear_enough(&pitch) {
+                // only the note readout follows, the trail stays on the previous pitch
+                new_point = previous_point;
+            }
+        }
+        if should_detect {
+            new_point.is_pitched = pitch.is_some();
+            if let Some(midi_output) = &mut model.midi_output {
+                let velocity = (model.current_level.clamp(0.0, 1.0) * 127.0) as u8;
+                midi_output.update(detected_midi, velocity);
+            }
+        }
+
+        new_point.pos.y += Y_PER_SECOND * settings.time_scale * block_time;
+        new_point.pos.z += Z_PER_SECOND * settings.time_scale * block_time;
+        new_point.time += block_time;
+
+        let chord_points: Vec<TrailPoint> = pitches
+            .iter()
+            .skip(1)
+            .filter(|pitch| is_clear_enough(*pitch))
+            .map(|pitch| {
+                let mut midi = freq_to_midi_float(pitch.frequency, settings.a4_reference);
+                if settings.snap_to_tuning {
+                    if let Some(snapped) = nearest_playable_note(midi, &model.tab_table) {
+                        midi = snapped as f32;
+                    }
+                }
+                TrailPoint {
+                    pos: Vec3::new(midi_to_x(midi, model), new_point.pos.y, new_point.pos.z),
+                    midi,
+                    clarity: pitch.clarity,
+                    is_pitched: true,
+                    time: new_point.time,
+                }
+            })
+            .collect();
+
+        if model.locations.len() == model.locations.capacity() {
+            // takes and sessions are saved as a whole, however long they get
+            let is_take = model.take_path.is_some() || model.session_active;
+            if is_take && model.leading_silent_points == 0 {
+                model.scrolled_off_points.push(model.locations[0]);
             }
-            new_pos.y -= 0.1;
-            new_pos.z += 0.3;
+            model.locations.rotate_left(1);
+            model.locations.pop();
+            model.leading_silent_points = model.leading_silent_points.saturating_sub(1);
+        }
+        // in auto session mode the trail only grows during a take
+        let is_recording = !settings.auto_session || model.session_active;
+        if model.is_running && is_recording && !model.paused {
+            model.head = new_point;
 
-            if model.locations.len() == model.locations.capacity() {
-                model.locations.rotate_left(1);
-                model.locations.pop();
+            // lag compensation only shifts pitch along the time axis, not the y/z advance
+            let mut committed = new_point;
+            if lag_blocks > 0 {
+                model.delayed_pitches.push_back((
+                    new_point.pos.x,
+                    new_point.midi,
+                    new_point.clarity,
+                ));
+                while model.delayed_pitches.len() > lag_blocks as usize + 1 {
+                    model.delayed_pitches.pop_front();
+                }
+                let (x, midi, clarity) = model.delayed_pitches[0];
+                committed.pos.x = x;
+                committed.midi = midi;
+                committed.clarity = clarity;
+            } else if lag_blocks < 0 && detected_midi.is_some() {
+                // show the detection on the points committed before it was available
+                for point in model
+                    .locations
+                    .iter_mut()
+                    .rev()
+                    .take(lag_blocks.unsigned_abs())
+                {
+                    point.pos.x = new_point.pos.x;
+                    point.midi = new_point.midi;
+                    point.clarity = new_point.clarity;
+                }
             }
-            if model.is_running {
-                model.locations.push(new_pos);
+
+            model.locations.push(committed);
+            model.chord_points.extend(chord_points);
+            // drop chord points older than the start of the trail
+            let oldest_z = model.locations[0].pos.z;
+            model.chord_points.retain(|point| point.pos.z >= oldest_z);
+            if !model.has_pitch {
+                model.leading_silent_points += 1;
             }
+        }
+    }
+
+    if let Some(stream) = &model.monitor_stream {
+        if settings.monitor_enabled {
+            let frequency = midi_to_freq(model.head.midi, settings.a4_reference);
+            let amplitude = if model.has_pitch {
+                0.3 * model.current_level.min(1.0)
+            } else {
+                0.0
+            };
+            let pan = if settings.monitor_pan {
+                let pan = map_range(
+                    model.head.pos.x,
+                    model.line_bounds[0],
+                    model.line_bounds[1],
+                    0.0,
+                    1.0,
+                );
+                // follow the note's position on screen
+                if settings.mirror_x {
+                    1.0 - pan
+                } else {
+                    pan
+                }
+            } else {
+                0.5
+            };
+            stream
+                .send(move |monitor| {
+                    monitor.frequency = frequency;
+                    monitor.target_amplitude = amplitude;
+                    monitor.pan = pan;
+                })
+                .ok();
+        }
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(server) = &mut model.http_server {
+        if is_pitch_current(model) {
+            server.publish(
+                &model.current_note,
+                model.current_midi,
+                model.current_frequency,
+                model.current_clarity,
+                model.current_level,
+            );
+        } else {
+            server.publish_silence(model.current_level);
+        }
+    }
+
+    if model.settings.auto_session {
+        update_auto_session(app, model, update.since_last.as_secs_f32());
+    }
 
-            buf.clear();
+    if let Some(practice) = &mut model.practice {
+        if model.is_running {
+            // the note readout keeps the last note through silence
+            let played = if is_pitch_current(model) {
+                native_tab(&model.current_note)
+            } else {
+                String::new()
+            };
+            practice.update(update.since_last.as_secs_f32(), &played);
         }
     }
 
-    let mut direction = new_pos - model.camera_pos;
+    let mut direction = new_point.pos - model.camera.pos;
     direction.x = 0.0;
-    model.camera_pos += direction;
+    model.camera.pos += direction;
 }
 
 fn ui(model: &mut Model, update: Update) {
@@ -190,24 +1284,87 @@ fn ui(model: &mut Model, update: Update) {
 
     if model.ui_visible {
         egui::Window::new("Settings").show(&ctx, |ui| {
-            ui.label("Power threshold:");
-            ui.add(egui::Slider::new(&mut settings.power_threshold, 0.0..=5.0));
+            ui.checkbox(&mut settings.auto_threshold, "auto power threshold");
+            if settings.auto_threshold {
+                ui.add(
+                    egui::Slider::new(&mut settings.threshold_margin, 1.0..=10.0)
+                        .text("margin above noise floor"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.threshold_adapt_rate, 0.001..=0.5)
+                        .logarithmic(true)
+                        .text("adapt rate"),
+                );
+                ui.label(format!(
+                    "Power threshold: {:.2} (noise floor {:.3})",
+                    model.power_threshold, model.noise_floor
+                ));
+            } else {
+                ui.label("Power threshold:");
+                ui.add(egui::Slider::new(&mut settings.power_threshold, 0.0..=5.0));
+            }
+
+            egui::ComboBox::from_label("Polyphony")
+                .selected_text(settings.polyphony_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in PolyphonyMode::ALL {
+                        ui.selectable_value(&mut settings.polyphony_mode, mode, mode.name());
+                    }
+                });
+            if settings.polyphony_mode == PolyphonyMode::Poly {
+                ui.add(egui::Slider::new(&mut settings.max_voices, 2..=6).text("max voices"));
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("A4 reference (Hz):");
+                let response = ui.add(
+                    egui::DragValue::new(&mut settings.a4_reference)
+                        .clamp_range(A4_RANGE)
+                        .speed(0.1),
+                );
+                if response.changed() {
+                    let contents = format!("{}\n", settings.a4_reference);
+                    if let Err(e) = std::fs::write(A4_REFERENCE_PATH, contents) {
+                        println!("failed to save A4 reference: {}", e);
+                    }
+                }
+            });
 
             ui.label("Clarity threshold:");
             ui.add(egui::Slider::new(
                 &mut settings.clarity_threshold,
                 0.0..=1.0,
             ));
+            ui.label("Trail clarity threshold:");
+            ui.add(egui::Slider::new(
+                &mut settings.trail_clarity_threshold,
+                0.0..=1.0,
+            ));
+            if settings.trail_clarity_threshold > 0.0 {
+                ui.checkbox(
+                    &mut settings.unclear_updates_note,
+                    "show notes below trail threshold",
+                );
+            }
+
+            egui::ComboBox::from_label("Mode")
+                .selected_text(settings.visualization_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in VisualizationMode::ALL {
+                        ui.selectable_value(&mut settings.visualization_mode, mode, mode.name());
+                    }
+                });
 
-            let keys = [
-                "C", "G", "D", "A", "E", "B", "F#", "Db", "Ab", "Eb", "Bb", "F", "LF", "LC", "LD",
-                "HG",
-            ];
             egui::ComboBox::from_label("Key")
                 .selected_text(settings.key)
                 .show_ui(ui, |ui| {
-                    for key in keys.iter() {
+                    for key in KEYS.iter() {
                         if ui.selectable_value(&mut settings.key, key, key).changed() {
+                            model.tab_table = build_tab_table(
+                                settings.key,
+                                &model.tuning_notes,
+                                settings.notation,
+                            );
                             if settings.should_calc_bounds_from_key {
                                 model.midi_bounds = calc_freq_bounds(settings.key);
                             }
@@ -215,31 +1372,76 @@ fn ui(model: &mut Model, update: Update) {
                     }
                 });
 
-            let tunings = [
-                "richter",
-                "country",
-                "wilde tuning",
-                "wilde minor tuning",
-                "melody maker",
-                "natural minor",
-                "harmonic minor",
-                "paddy richter",
-                "pentaharp",
-                "powerdraw",
-                "powerbender",
-                "diminished",
-                "easy 3rd",
-            ];
             egui::ComboBox::from_label("Tuning")
                 .selected_text(settings.tuning)
                 .width(150.0)
                 .show_ui(ui, |ui| {
-                    for tuning in tunings.iter() {
+                    for tuning in TUNINGS.iter() {
                         if ui
                             .selectable_value(&mut settings.tuning, tuning, tuning)
                             .changed()
                         {
                             model.tuning_notes = harptabber::tuning_to_notes_in_order(tuning).0;
+                            if settings.auto_key_with_tuning {
+                                settings.key = get_tuning_default_key(tuning);
+                                if settings.should_calc_bounds_from_key {
+                                    model.midi_bounds = calc_freq_bounds(settings.key);
+                                }
+                            }
+                            model.tab_table = build_tab_table(
+                                settings.key,
+                                &model.tuning_notes,
+                                settings.notation,
+                            );
+                        }
+                    }
+                });
+
+            egui::ComboBox::from_label("Tab notation")
+                .selected_text(settings.notation.name())
+                .width(150.0)
+                .show_ui(ui, |ui| {
+                    for notation in NotationStyle::ALL {
+                        if ui
+                            .selectable_value(&mut settings.notation, notation, notation.name())
+                            .changed()
+                        {
+                            model.tab_table = build_tab_table(
+                                settings.key,
+                                &model.tuning_notes,
+                                settings.notation,
+                            );
+                            model.current_note = notation.format(&model.current_note);
+                        }
+                    }
+                });
+            match infer_position(&model.note_counts, settings.key) {
+                Some((position, tonic)) => ui.label(format!(
+                    "Position: {} ({})",
+                    position_name(position),
+                    pitch_class_name(tonic)
+                )),
+                None => ui.label("Position: play some notes"),
+            };
+
+            ui.checkbox(
+                &mut settings.snap_to_tuning,
+                "snap to notes playable in tuning",
+            );
+            ui.checkbox(
+                &mut settings.auto_key_with_tuning,
+                "auto-set key with tuning",
+            );
+
+            egui::ComboBox::from_label("Theme")
+                .selected_text(&settings.theme)
+                .show_ui(ui, |ui| {
+                    for theme in model.themes.iter() {
+                        if ui
+                            .selectable_value(&mut settings.theme, theme.name.clone(), &theme.name)
+                            .changed()
+                        {
+                            apply_theme(settings, theme);
                         }
                     }
                 });
@@ -248,36 +1450,862 @@ fn ui(model: &mut Model, update: Update) {
                 edit_hsv(ui, &mut settings.left_color);
                 ui.label("Left color");
             });
+            ui.horizontal(|ui| {
+                edit_hsv(ui, &mut settings.center_color);
+                ui.label("Center color");
+            });
             ui.horizontal(|ui| {
                 edit_hsv(ui, &mut settings.right_color);
                 ui.label("Right color");
             });
+            ui.horizontal(|ui| {
+                edit_hsv(ui, &mut settings.background_color);
+                ui.label("Background color");
+            });
+            ui.horizontal(|ui| {
+                edit_hsv(ui, &mut settings.note_color);
+                ui.label("Note color");
+            });
 
-            if ui
-                .checkbox(
-                    &mut settings.should_calc_bounds_from_key,
-                    "calculate bounds from key",
-                )
-                .changed()
-            {
-                if settings.should_calc_bounds_from_key {
-                    model.midi_bounds = calc_freq_bounds(settings.key);
-                } else {
-                    model.midi_bounds = MidiBounds::default();
-                }
-            }
+            ui.label("Detection hop size (samples):");
+            ui.add(egui::Slider::new(&mut settings.hop_size, 64..=SIZE));
 
-            if ui.button("reset").clicked() {
-                model.locations.clear();
-                model.is_running = false;
-            }
+            ui.label("Detect every Nth block:");
+            ui.add(egui::Slider::new(&mut settings.detection_interval, 1..=8));
+            ui.label(format!(
+                "Buffer fill: {:.0}%",
+                model.ring_buffer_fill * 100.0
+            ))
+            .on_hover_text("increase N if this keeps growing");
 
-            ui.label("F1 to hide");
-        });
-    }
-}
+            edit_optional_frequency(
+                ui,
+                &mut settings.high_pass_cutoff,
+                "high-pass",
+                20.0..=1000.0,
+                80.0,
+            );
+            edit_optional_frequency(
+                ui,
+                &mut settings.low_pass_cutoff,
+                "low-pass",
+                1000.0..=10000.0,
+                4000.0,
+            );
 
-fn edit_hsv(ui: &mut egui::Ui, color: &mut LinSrgb) {
+            edit_optional_frequency(
+                ui,
+                &mut settings.test_tone,
+                "test tone",
+                100.0..=3000.0,
+                440.0,
+            );
+            ui.checkbox(&mut settings.keyboard_input, "keyboard input")
+                .on_hover_text("play notes on A W S E D F T G Y H U J K, Z and X change octave");
+            if let Some(frequency) = settings.test_tone {
+                let midi = freq_to_midi(frequency, settings.a4_reference);
+                ui.label(format!(
+                    "expected: {} ({})",
+                    midi_to_note_name(midi),
+                    model.tab_table[midi as usize]
+                ));
+            }
+
+            ui.label("Note dead zone (cents):");
+            ui.add(egui::Slider::new(&mut settings.dead_zone_cents, 0.0..=40.0));
+
+            ui.label("Lag compensation (ms):");
+            ui.add(
+                egui::DragValue::new(&mut settings.lag_compensation_ms)
+                    .clamp_range(-500.0..=500.0)
+                    .speed(1.0),
+            )
+            .on_hover_text("negative values move the trail earlier, positive values delay it");
+
+            ui.label("Level decay time (s):");
+            ui.add(egui::Slider::new(
+                &mut settings.level_decay_time,
+                0.01..=2.0,
+            ));
+
+            let mut is_capped = settings.fps_cap.is_some();
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut is_capped, "cap frame rate").changed() {
+                    settings.fps_cap = if is_capped { Some(60.0) } else { None };
+                }
+                if let Some(fps) = &mut settings.fps_cap {
+                    ui.add(egui::Slider::new(fps, 10.0..=144.0).text("fps"));
+                }
+            });
+
+            ui.label("Note font size:");
+            ui.add(egui::Slider::new(&mut settings.note_font_size, 16..=256));
+            egui::ComboBox::from_label("Note position")
+                .selected_text(settings.note_anchor.name())
+                .show_ui(ui, |ui| {
+                    for anchor in NoteAnchor::ALL {
+                        ui.selectable_value(&mut settings.note_anchor, anchor, anchor.name());
+                    }
+                });
+
+            ui.label("Trail fade time (s):");
+            ui.add(egui::Slider::new(&mut settings.fade_time, 0.05..=5.0));
+            ui.add(
+                egui::Slider::new(&mut settings.time_scale, 0.25..=4.0)
+                    .logarithmic(true)
+                    .text("time scale"),
+            );
+
+            ui.checkbox(&mut settings.soft_clip, "soft clip analysis input")
+                .on_hover_text("stops detection locking onto harmonics of clipped input");
+            if settings.polyphony_mode == PolyphonyMode::Mono {
+                egui::ComboBox::from_label("Window function")
+                    .selected_text(settings.window_function.name())
+                    .show_ui(ui, |ui| {
+                        for window in WindowFunction::ALL {
+                            ui.selectable_value(
+                                &mut settings.window_function,
+                                window,
+                                window.name(),
+                            );
+                        }
+                    });
+                if settings.window_function != WindowFunction::Rectangular {
+                    ui.label("less leakage, but the block edges count less (power, fast notes)");
+                }
+            }
+            ui.checkbox(
+                &mut settings.subharmonic_correction,
+                "correct octave jumps from recent notes",
+            )
+            .on_hover_text("halves detections an octave above the recent average");
+            if let Some(stream) = &model.monitor_stream {
+                if ui
+                    .checkbox(&mut settings.monitor_enabled, "monitor tone")
+                    .changed()
+                {
+                    let result = if settings.monitor_enabled {
+                        stream.play()
+                    } else {
+                        stream.pause()
+                    };
+                    if let Err(e) = result {
+                        println!("failed to toggle monitor: {}", e);
+                        settings.monitor_enabled = false;
+                    }
+                }
+                if settings.monitor_enabled {
+                    ui.checkbox(&mut settings.monitor_pan, "pan monitor by note position");
+                }
+            }
+            let mut show_grid = settings.grid_interval.is_some();
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut show_grid, "time gridlines").changed() {
+                    settings.grid_interval = if show_grid { Some(1.0) } else { None };
+                }
+                if let Some(interval) = &mut settings.grid_interval {
+                    ui.add(egui::Slider::new(interval, 0.25..=10.0).text("s"));
+                }
+            });
+            ui.checkbox(&mut settings.depth_shading, "depth shading");
+            egui::ComboBox::from_label("Line style")
+                .selected_text(settings.line_style.name())
+                .show_ui(ui, |ui| {
+                    for style in LineStyle::ALL {
+                        ui.selectable_value(&mut settings.line_style, style, style.name());
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut settings.clarity_shading, "clarity shading");
+                if settings.clarity_shading {
+                    ui.add(
+                        egui::Slider::new(&mut settings.clarity_shading_strength, 0.0..=1.0)
+                            .text("strength"),
+                    );
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut settings.mirror_x, "mirror x");
+                ui.checkbox(&mut settings.invert_y, "invert y");
+                ui.checkbox(&mut settings.invert_z, "invert z");
+            });
+            ui.checkbox(&mut settings.start_running, "start running immediately");
+            ui.checkbox(&mut settings.show_legend, "show color legend");
+            ui.checkbox(&mut settings.show_histogram, "show note histogram");
+            ui.checkbox(&mut settings.show_spectrogram, "show spectrogram")
+                .on_hover_text("what the detector sees, with the detected pitch marked");
+            ui.checkbox(&mut settings.idle_animation, "idle animation");
+            egui::ComboBox::from_label("Color mode")
+                .selected_text(settings.color_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in ColorMode::ALL {
+                        ui.selectable_value(&mut settings.color_mode, mode, mode.name());
+                    }
+                });
+            egui::ComboBox::from_label("X axis scale")
+                .selected_text(settings.x_axis_scale.name())
+                .show_ui(ui, |ui| {
+                    for scale in XAxisScale::ALL {
+                        ui.selectable_value(&mut settings.x_axis_scale, scale, scale.name());
+                    }
+                });
+
+            ui.collapsing("Pitch class colors", |ui| {
+                let mut changed = false;
+                for (pitch_class, color) in settings.pitch_colors.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        changed |= edit_hsv(ui, color);
+                        ui.label(pitch_class_name(pitch_class as u8));
+                    });
+                }
+                if ui.button("reset to default rainbow").clicked() {
+                    settings.pitch_colors = pitch_colors::default_rainbow();
+                    changed = true;
+                }
+                if changed {
+                    if let Err(e) =
+                        pitch_colors::save(pitch_colors::PITCH_COLORS_PATH, &settings.pitch_colors)
+                    {
+                        println!("failed to save pitch class colors: {}", e);
+                    }
+                }
+            });
+
+            if ui
+                .checkbox(
+                    &mut settings.should_calc_bounds_from_key,
+                    "calculate bounds from key",
+                )
+                .changed()
+            {
+                if settings.should_calc_bounds_from_key {
+                    model.midi_bounds = calc_freq_bounds(settings.key);
+                } else {
+                    model.midi_bounds = MidiBounds::default();
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("reset").clicked() {
+                    model.pending_actions.push(Action::Reset);
+                }
+                let label = if model.take_path.is_some() {
+                    "stop take"
+                } else {
+                    "record take"
+                };
+                if ui
+                    .button(label)
+                    .on_hover_text("resets, then records the trail and a detection log")
+                    .clicked()
+                {
+                    model.pending_actions.push(Action::ToggleTake);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                for format in ExportFormat::ALL {
+                    if ui.button(format!("export {}", format.name())).clicked() {
+                        model.pending_actions.push(Action::Export(format));
+                    }
+                }
+            });
+            ui.add(
+                egui::Slider::new(&mut settings.rest_threshold, 0.1..=5.0)
+                    .text("s of silence exported as a rest"),
+            );
+
+            ui.collapsing("Key bindings", |ui| {
+                let mut changed = false;
+                for (action, bound) in settings.keybindings.iter_mut() {
+                    let selected = bound.map_or("none", keybindings::key_name);
+                    egui::ComboBox::from_label(action.name())
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            changed |= ui.selectable_value(bound, None, "none").changed();
+                            for (key, name) in keybindings::BINDABLE_KEYS {
+                                changed |= ui.selectable_value(bound, Some(key), name).changed();
+                            }
+                        });
+                }
+                if ui.button("reset to defaults").clicked() {
+                    settings.keybindings = keybindings::defaults();
+                    changed = true;
+                }
+                if changed {
+                    if let Err(e) =
+                        keybindings::save(keybindings::KEYBINDINGS_PATH, &settings.keybindings)
+                    {
+                        println!("failed to save key bindings: {}", e);
+                    }
+                }
+            });
+
+            ui.collapsing("Auto session", |ui| {
+                ui.checkbox(&mut settings.auto_session, "record takes automatically");
+                ui.add(
+                    egui::Slider::new(&mut settings.session_start_level, 0.0..=1.0)
+                        .text("start level"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.session_stop_level, 0.0..=1.0)
+                        .text("stop level"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.session_hangover, 0.5..=10.0)
+                        .text("hangover (s)"),
+                );
+                let selected_text = settings.session_export.map_or("nothing", |f| f.name());
+                egui::ComboBox::from_label("Save each take as")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut settings.session_export, None, "nothing");
+                        for format in ExportFormat::ALL {
+                            ui.selectable_value(
+                                &mut settings.session_export,
+                                Some(format),
+                                format.name(),
+                            );
+                        }
+                    });
+                if settings.auto_session {
+                    ui.label(if model.session_active {
+                        "recording take"
+                    } else {
+                        "waiting for input"
+                    });
+                }
+            });
+
+            ui.collapsing("Camera", |ui| {
+                let viewpoint = &mut model.camera.viewpoint;
+                ui.add(egui::Slider::new(&mut viewpoint.zoom, 0.1..=5.0).text("zoom"));
+                ui.add(egui::Slider::new(&mut viewpoint.offset.x, -20.0..=20.0).text("offset x"));
+                ui.add(egui::Slider::new(&mut viewpoint.offset.y, -20.0..=20.0).text("offset y"));
+                ui.checkbox(&mut settings.keep_camera_on_reset, "keep camera on reset");
+                ui.horizontal(|ui| {
+                    if ui.button("save viewpoint").clicked() {
+                        model.saved_viewpoint = Some(model.camera.viewpoint);
+                    }
+                    if let Some(saved) = model.saved_viewpoint {
+                        if ui.button("restore viewpoint").clicked() {
+                            model.camera.viewpoint = saved;
+                        }
+                    }
+                });
+            });
+
+            ui.collapsing("Ghost trail", |ui| {
+                ui.label("Note list file:");
+                ui.text_edit_singleline(&mut settings.ghost_path);
+                ui.horizontal(|ui| {
+                    if ui.button("load").clicked() {
+                        match export::read_note_list(&settings.ghost_path) {
+                            Ok(notes) => {
+                                model.ghost_status = format!("loaded {} notes", notes.len());
+                                model.ghost = notes;
+                            }
+                            Err(e) => {
+                                model.ghost_status = format!("failed to load note list: {}", e);
+                            }
+                        }
+                    }
+                    if ui.button("clear").clicked() {
+                        model.ghost.clear();
+                        model.ghost_status.clear();
+                    }
+                });
+                ui.checkbox(&mut settings.show_ghost, "show ghost trail");
+                if !model.ghost_status.is_empty() {
+                    ui.label(&model.ghost_status);
+                }
+            });
+
+            ui.collapsing("Snapshots", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Label:");
+                    ui.text_edit_singleline(&mut settings.snapshot_label);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("capture").clicked() {
+                        model.pending_actions.push(Action::CaptureSnapshot);
+                    }
+                    if ui.button("clear").clicked() {
+                        model.snapshots.clear();
+                    }
+                });
+                ui.checkbox(&mut settings.show_snapshots, "show snapshots");
+                for snapshot in &model.snapshots {
+                    ui.label(format!(
+                        "{} ({} points)",
+                        snapshot.label,
+                        snapshot.points.len()
+                    ));
+                }
+            });
+
+            ui.collapsing("Practice", |ui| {
+                ui.label("Melody file (one tab and optional duration per line):");
+                ui.text_edit_singleline(&mut settings.melody_path);
+                ui.horizontal(|ui| {
+                    if ui.button("load").clicked() {
+                        match Practice::load(&settings.melody_path) {
+                            Ok(practice) => {
+                                model.practice_status =
+                                    format!("loaded {} notes", practice.note_count());
+                                model.practice = Some(practice);
+                            }
+                            Err(e) => {
+                                model.practice_status = format!("failed to load melody: {}", e);
+                            }
+                        }
+                    }
+                    if ui.button("stop").clicked() {
+                        model.practice = None;
+                        model.practice_status.clear();
+                    }
+                });
+                if !model.practice_status.is_empty() {
+                    ui.label(&model.practice_status);
+                }
+                if let Some(practice) = &model.practice {
+                    ui.label(format!(
+                        "hits: {}, misses: {}",
+                        practice.hits, practice.misses
+                    ));
+                    if practice.is_finished() {
+                        ui.label("finished!");
+                    }
+                }
+            });
+
+            ui.collapsing("Detection log", |ui| {
+                ui.label("Every detection with its time, frequency, clarity and level, as CSV:");
+                ui.horizontal(|ui| {
+                    ui.label("File (timestamped if empty):");
+                    ui.text_edit_singleline(&mut settings.detection_log_path);
+                });
+                let label = if model.detection_log.is_some() {
+                    "stop logging"
+                } else {
+                    "start logging"
+                };
+                if ui.button(label).clicked() {
+                    model.pending_actions.push(Action::ToggleDetectionLog);
+                }
+                if !model.detection_log_status.is_empty() {
+                    ui.label(&model.detection_log_status);
+                }
+            });
+
+            ui.collapsing("OSC output", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Host:");
+                    ui.text_edit_singleline(&mut settings.osc_host);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Port:");
+                    ui.add(egui::DragValue::new(&mut settings.osc_port));
+                });
+                if ui
+                    .checkbox(&mut settings.osc_enabled, "send detections over OSC")
+                    .changed()
+                {
+                    if settings.osc_enabled {
+                        match OscOutput::connect(&settings.osc_host, settings.osc_port) {
+                            Ok(osc_output) => {
+                                model.osc_status = format!(
+                                    "sending to {}:{}",
+                                    settings.osc_host, settings.osc_port
+                                );
+                                model.osc_output = Some(osc_output);
+                            }
+                            Err(e) => {
+                                settings.osc_enabled = false;
+                                model.osc_status = format!("failed to connect: {}", e);
+                            }
+                        }
+                    } else {
+                        model.osc_output = None;
+                        model.osc_status.clear();
+                    }
+                }
+                if !model.osc_status.is_empty() {
+                    ui.label(&model.osc_status);
+                }
+            });
+
+            ui.collapsing("MIDI output", |ui| {
+                let selected_text = settings
+                    .midi_port
+                    .clone()
+                    .unwrap_or_else(|| "off".to_owned());
+                egui::ComboBox::from_label("MIDI port")
+                    .selected_text(selected_text)
+                    .width(150.0)
+                    .show_ui(ui, |ui| {
+                        let mut changed = ui
+                            .selectable_value(&mut settings.midi_port, None, "off")
+                            .changed();
+                        for port in model.midi_ports.iter() {
+                            changed |= ui
+                                .selectable_value(&mut settings.midi_port, Some(port.clone()), port)
+                                .changed();
+                        }
+                        if changed {
+                            // drop the old connection first so its note gets released
+                            model.midi_output = None;
+                            model.midi_status.clear();
+                            if let Some(port) = &settings.midi_port {
+                                match MidiOutput::connect(port) {
+                                    Ok(midi_output) => model.midi_output = Some(midi_output),
+                                    Err(e) => {
+                                        model.midi_status = format!("failed to connect: {}", e);
+                                        settings.midi_port = None;
+                                    }
+                                }
+                            }
+                        }
+                    });
+                if ui.button("refresh ports").clicked() {
+                    model.midi_ports = midi_out::list_ports();
+                }
+                if !model.midi_status.is_empty() {
+                    ui.label(&model.midi_status);
+                }
+            });
+
+            if let Some(key) = keybindings::key_for(&settings.keybindings, Action::ToggleUi) {
+                ui.label(format!("{} to hide", keybindings::key_name(key)));
+            }
+        });
+    }
+}
+
+fn edit_optional_frequency(
+    ui: &mut egui::Ui,
+    frequency: &mut Option<f32>,
+    name: &str,
+    range: RangeInclusive<f32>,
+    default: f32,
+) {
+    ui.horizontal(|ui| {
+        let mut enabled = frequency.is_some();
+        if ui.checkbox(&mut enabled, name).changed() {
+            *frequency = if enabled { Some(default) } else { None };
+        }
+        if let Some(frequency) = frequency {
+            ui.add(
+                egui::Slider::new(frequency, range)
+                    .logarithmic(true)
+                    .text("Hz"),
+            );
+        }
+    });
+}
+
+fn apply_theme(settings: &mut Settings, theme: &Theme) {
+    settings.background_color = theme.background;
+    settings.left_color = theme.left;
+    settings.center_color = theme.center;
+    settings.right_color = theme.right;
+    settings.note_color = theme.note;
+}
+
+/// Runs an action requested from the UI or a key binding.
+fn perform_action(app: &App, model: &mut Model, action: Action) {
+    match action {
+        Action::Reset => reset(model),
+        Action::Export(ExportFormat::Svg) => export_trail_svg(model),
+        Action::Export(ExportFormat::Obj) => export_trail_obj(model),
+        Action::Export(ExportFormat::NoteList) => export_note_list(model),
+        Action::ToggleUi => model.ui_visible = !model.ui_visible,
+        Action::TogglePause => model.paused = !model.paused,
+        Action::Screenshot => {
+            let path = export::timestamped_path("screenshot", "png");
+            app.main_window().capture_frame(&path);
+            println!("saved screenshot to {}", path.display());
+        }
+        Action::ToggleFullscreen => {
+            let window = app.main_window();
+            window.set_fullscreen(!window.is_fullscreen());
+        }
+        Action::NextKey => {
+            let index = KEYS.iter().position(|&key| key == model.settings.key);
+            model.settings.key = KEYS[index.map_or(0, |index| (index + 1) % KEYS.len())];
+            model.tab_table = build_tab_table(
+                model.settings.key,
+                &model.tuning_notes,
+                model.settings.notation,
+            );
+            if model.settings.should_calc_bounds_from_key {
+                model.midi_bounds = calc_freq_bounds(model.settings.key);
+            }
+        }
+        Action::NextTuning => {
+            let settings = &mut model.settings;
+            let index = TUNINGS.iter().position(|&tuning| tuning == settings.tuning);
+            settings.tuning = TUNINGS[index.map_or(0, |index| (index + 1) % TUNINGS.len())];
+            model.tuning_notes = harptabber::tuning_to_notes_in_order(settings.tuning).0;
+            if settings.auto_key_with_tuning {
+                settings.key = get_tuning_default_key(settings.tuning);
+                if settings.should_calc_bounds_from_key {
+                    model.midi_bounds = calc_freq_bounds(settings.key);
+                }
+            }
+            model.tab_table = build_tab_table(settings.key, &model.tuning_notes, settings.notation);
+        }
+        Action::CaptureSnapshot => capture_snapshot(model),
+        Action::ToggleDetectionLog => toggle_detection_log(model),
+        Action::ToggleTake => toggle_take(model),
+    }
+}
+
+/// Freezes the trail as it's currently drawn, replacing the oldest snapshot
+/// once `MAX_SNAPSHOTS` are kept.
+fn capture_snapshot(model: &mut Model) {
+    let points = visible_trail(model)
+        .iter()
+        .map(|point| screen_position(point, model))
+        .collect();
+    let label = if model.settings.snapshot_label.is_empty() {
+        format!("snapshot {}", model.snapshots.len() + 1)
+    } else {
+        model.settings.snapshot_label.clone()
+    };
+    if model.snapshots.len() == MAX_SNAPSHOTS {
+        model.snapshots.remove(0);
+    }
+    model.snapshots.push(Snapshot { label, points });
+}
+
+/// Starts a fresh take when the level rises above the start threshold, and ends
+/// it once the level stayed below the stop threshold for the hangover time.
+fn update_auto_session(app: &App, model: &mut Model, delta: f32) {
+    let settings = &model.settings;
+    if !model.session_active {
+        if model.current_level > settings.session_start_level {
+            reset(model);
+            model.session_active = true;
+            model.session_quiet_time = 0.0;
+        }
+        return;
+    }
+
+    if model.current_level >= settings.session_stop_level {
+        model.session_quiet_time = 0.0;
+        return;
+    }
+    model.session_quiet_time += delta;
+    if model.session_quiet_time >= settings.session_hangover {
+        model.session_active = false;
+        if let Some(format) = model.settings.session_export {
+            perform_action(app, model, Action::Export(format));
+        }
+    }
+}
+
+/// Whether a pitch was detected within about the last analysed block, i.e. the
+/// current note is still being played.
+fn is_pitch_current(model: &Model) -> bool {
+    let settings = &model.settings;
+    let block_time = (settings.hop_size * settings.detection_interval) as f32 / SAMPLE_RATE as f32;
+    model.has_pitch && model.seconds_since_detection <= block_time
+}
+
+/// Trail length per second of playing at the current time scale.
+fn z_per_second(model: &Model) -> f32 {
+    Z_PER_SECOND * model.settings.time_scale
+}
+
+/// Merges consecutive trail points with the same note into note events. Times
+/// count from the start of the trail, including the silence before the first
+/// detection, so the notes stay in sync with a recording started at the same time.
+fn trail_note_events(model: &Model) -> Vec<export::NoteEvent> {
+    let mut notes: Vec<export::NoteEvent> = Vec::new();
+    for point in &recorded_trail(model) {
+        let midi = point.is_pitched.then(|| point.midi.round() as u8);
+        let time = point.time;
+        match notes.last_mut() {
+            Some(note) if note.midi == midi => note.duration = time - note.start,
+            _ => notes.push(export::NoteEvent {
+                start: time,
+                duration: 0.0,
+                midi,
+                tab: match midi {
+                    Some(midi) => model.tab_table[midi as usize].clone(),
+                    None => export::REST.to_owned(),
+                },
+            }),
+        }
+    }
+
+    export::merge_short_rests(notes, model.settings.rest_threshold)
+}
+
+/// What the detection thread needs from the settings, see `DetectionSettings`.
+fn detection_settings(model: &Model) -> DetectionSettings {
+    let settings = &model.settings;
+    DetectionSettings {
+        hop_size: settings.hop_size,
+        detection_interval: settings.detection_interval,
+        power_threshold: model.power_threshold,
+        clarity_threshold: settings.clarity_threshold,
+        a4_reference: settings.a4_reference,
+        polyphony_mode: settings.polyphony_mode,
+        max_voices: settings.max_voices,
+        soft_clip: settings.soft_clip,
+        window_function: settings.window_function,
+        high_pass_cutoff: settings.high_pass_cutoff,
+        low_pass_cutoff: settings.low_pass_cutoff,
+        test_tone: settings.test_tone,
+        level_decay_time: settings.level_decay_time,
+        keyboard_note: settings.keyboard_input.then_some(model.keyboard_midi),
+        show_spectrogram: settings.show_spectrogram,
+    }
+}
+
+fn toggle_detection_log(model: &mut Model) {
+    model.detection_log_status = match model.detection_log.take() {
+        Some(log) => {
+            let row_count = log.row_count;
+            match log.finish() {
+                Ok(()) => format!("logged {} detections", row_count),
+                Err(e) => format!("failed to write detection log: {}", e),
+            }
+        }
+        None => {
+            let path = if model.settings.detection_log_path.is_empty() {
+                export::timestamped_path("detections", "csv")
+            } else {
+                PathBuf::from(&model.settings.detection_log_path)
+            };
+            match DetectionLog::create(&path) {
+                Ok(log) => {
+                    model.detection_log = Some(log);
+                    format!("logging to {}", path.display())
+                }
+                Err(e) => format!("failed to create detection log: {}", e),
+            }
+        }
+    };
+    println!("{}", model.detection_log_status);
+}
+
+/// Starts a take by clearing the trail and logging detections, or ends the
+/// current one by saving its notes, e.g. to `take-1678900000.csv` and
+/// `take-1678900000-detections.csv`.
+fn toggle_take(model: &mut Model) {
+    if let Some(path) = model.take_path.take() {
+        let notes = trail_note_events(model);
+        match export::write_note_list(&path, &notes) {
+            Ok(()) => println!("saved take to {}", path.display()),
+            Err(e) => println!("failed to save take: {}", e),
+        }
+        model.scrolled_off_points.clear();
+        if model.detection_log.is_some() {
+            toggle_detection_log(model);
+        }
+        return;
+    }
+
+    if model.detection_log.is_some() {
+        // the take gets a log of its own
+        toggle_detection_log(model);
+    }
+    reset(model);
+    let path = export::timestamped_path("take", "csv");
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let log_path = path.with_file_name(format!("{}-detections.csv", stem));
+    model.detection_log_status = match DetectionLog::create(&log_path) {
+        Ok(log) => {
+            model.detection_log = Some(log);
+            format!("logging to {}", log_path.display())
+        }
+        Err(e) => format!("failed to create detection log: {}", e),
+    };
+    println!("recording take {}", path.display());
+    model.take_path = Some(path);
+}
+
+fn export_note_list(model: &Model) {
+    let notes = trail_note_events(model);
+    if notes.is_empty() {
+        println!("nothing to export, the trail is empty");
+        return;
+    }
+    let path = export::timestamped_path("notes", "csv");
+    match export::write_note_list(&path, &notes) {
+        Ok(()) => println!("exported notes to {}", path.display()),
+        Err(e) => println!("failed to export notes: {}", e),
+    }
+}
+
+fn export_trail_svg(model: &Model) {
+    let trail = recorded_trail(model);
+    if trail.is_empty() {
+        println!("nothing to export, the trail is empty");
+        return;
+    }
+    let points: Vec<(Vec2, LinSrgb)> = trail
+        .iter()
+        .map(|point| (screen_position(point, model), point_color(point, model)))
+        .collect();
+    let path = export::timestamped_path("trail", "svg");
+    match export::write_svg(&path, &points, 10.0 * model.current_level + 1.0) {
+        Ok(()) => println!("exported trail to {}", path.display()),
+        Err(e) => println!("failed to export trail: {}", e),
+    }
+}
+
+fn export_trail_obj(model: &Model) {
+    let trail = recorded_trail(model);
+    if trail.is_empty() {
+        println!("nothing to export, the trail is empty");
+        return;
+    }
+    let points: Vec<(Vec3, LinSrgb)> = trail
+        .iter()
+        .map(|point| (point.pos, point_color(point, model)))
+        .collect();
+    let path = export::timestamped_path("trail", "obj");
+    match export::write_obj(&path, &points) {
+        Ok(()) => println!("exported trail to {}", path.display()),
+        Err(e) => println!("failed to export trail: {}", e),
+    }
+}
+
+/// Clears the trail and everything accumulated from it.
+fn reset(model: &mut Model) {
+    model.locations.clear();
+    model.chord_points.clear();
+    model.clear_screen = true;
+    model.is_running = model.settings.start_running;
+    model.has_pitch = false;
+    model.leading_silent_points = 0;
+    model.scrolled_off_points.clear();
+    model.note_counts = [0; 12];
+    model.recent_midi.clear();
+    model.displayed_midi = None;
+    if let Some(practice) = &mut model.practice {
+        practice.restart();
+    }
+
+    model.delayed_pitches.clear();
+    if model.settings.keep_camera_on_reset {
+        // continue from the current camera position so the view doesn't jump
+        model.head = TrailPoint {
+            pos: Vec3::new(0.0, model.camera.pos.y, model.camera.pos.z),
+            ..Default::default()
+        };
+    } else {
+        model.head = TrailPoint::default();
+        model.camera.viewpoint = Viewpoint::default();
+    }
+}
+
+/// Returns whether the color was changed.
+fn edit_hsv(ui: &mut egui::Ui, color: &mut LinSrgb) -> bool {
     let hsv_color: Hsv = Hsv::convert_from(*color);
     let mut egui_hsv = egui::color::Hsva::new(
         hsv_color.hue.to_positive_radians() / (std::f32::consts::PI * 2.0),
@@ -295,54 +2323,768 @@ fn edit_hsv(ui: &mut egui::Ui, color: &mut LinSrgb) {
     {
         let hsv = nannou::color::hsv(egui_hsv.h, egui_hsv.s, egui_hsv.v);
         *color = LinSrgb::convert_from(hsv);
+        return true;
     }
+    false
 }
 
 fn to_screen_position(point: &Vec3) -> Vec2 {
-    let z = point.z - 10.0;
-    // z is always negative
+    // z is always negative, points that passed the viewer (inverted z) end up far off screen
+    let z = (point.z - 10.0).min(-0.1);
     let x = point.x / (0.01 * -z);
     let y = point.y / (0.01 * -z);
     Vec2::new(10.0 * x, 10.0 * y)
 }
 
 fn from_camera_view(point: Vec3, model: &Model) -> Vec2 {
-    let point = point - model.camera_pos;
-    to_screen_position(&point)
+    let viewpoint = model.camera.viewpoint;
+    let point = view_position(point, model) - viewpoint.offset.extend(0.0);
+    to_screen_position(&point) * viewpoint.zoom
+}
+
+/// How far in front of the viewer the head is drawn with an inverted z axis
+const INVERTED_HEAD_DEPTH: f32 = 40.0;
+
+/// Position of a point relative to the camera, with the axis flips applied.
+fn view_position(point: Vec3, model: &Model) -> Vec3 {
+    let settings = &model.settings;
+    let mut point = point - model.camera.pos;
+    if settings.mirror_x {
+        point.x = -point.x;
+    }
+    if settings.invert_y {
+        point.y = -point.y;
+    }
+    if settings.invert_z {
+        point.z = -point.z - INVERTED_HEAD_DEPTH;
+    }
+    point
+}
+
+const CLOCK_RADIUS: f32 = 400.0;
+/// How far towards the center a point moves per unit of z (time) it falls behind
+const CLOCK_RADIAL_SPEED: f32 = 2.0;
+
+fn screen_position(point: &TrailPoint, model: &Model) -> Vec2 {
+    match model.settings.visualization_mode {
+        VisualizationMode::Spiral | VisualizationMode::Tuner => from_camera_view(point.pos, model),
+        VisualizationMode::Circular => {
+            let age = model.camera.pos.z - point.pos.z;
+            let radius = (CLOCK_RADIUS - age * CLOCK_RADIAL_SPEED).max(0.0);
+            clock_position(point.midi, radius)
+        }
+    }
+}
+
+/// Position on a chromatic clock face with C at the top, going clockwise.
+fn clock_position(midi: f32, radius: f32) -> Vec2 {
+    let angle = PI / 2.0 - midi.rem_euclid(12.0) / 12.0 * TAU;
+    Vec2::new(angle.cos(), angle.sin()) * radius
+}
+
+fn draw_clock_face(draw: &Draw) {
+    for pitch_class in 0..12u8 {
+        let pos = clock_position(pitch_class as f32, CLOCK_RADIUS + 40.0);
+        draw.text(pitch_class_name(pitch_class))
+            .xy(pos)
+            .font_size(20)
+            .color(GRAY);
+    }
+}
+
+/// Brightness/thickness factor in 0..=1 for a point, falling off with its
+/// distance from the camera.
+fn depth_factor(point: Vec3, model: &Model) -> f32 {
+    let z = view_position(point, model).z - 10.0;
+    (-10.0 / z).clamp(0.0, 1.0)
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
     let draw = app.draw();
-    if app.elapsed_frames() == 1 {
-        draw.background().color(BLACK);
+    // the trail is only faded out between frames, leftovers would flash after a reset
+    if app.elapsed_frames() == 1 || model.clear_screen {
+        draw.background().color(model.settings.background_color);
+    }
+
+    if model.settings.visualization_mode == VisualizationMode::Tuner {
+        draw.background().color(model.settings.background_color);
+        draw_tuner(&draw, model);
+    } else {
+        draw_trail(&draw, model, app.window_rect());
+    }
+
+    let is_idle = !model.has_pitch || model.seconds_since_detection > IDLE_DELAY;
+    if model.settings.idle_animation && is_idle {
+        draw_idle_hint(&draw, model, app.time);
+    }
+
+    if model.settings.show_snapshots && !model.snapshots.is_empty() {
+        draw_snapshots(&draw, &model.snapshots, app.window_rect());
+    }
+
+    if model.settings.show_legend {
+        draw_legend(&draw, model, app.window_rect());
+    }
+
+    if model.settings.show_histogram {
+        draw_histogram(
+            &draw,
+            &model.note_counts,
+            model.settings.note_color,
+            app.window_rect(),
+        );
+    }
+
+    if model.settings.show_spectrogram {
+        draw_spectrogram(
+            &draw,
+            &model.spectrogram,
+            model.settings.note_color,
+            app.window_rect(),
+        );
+    }
+
+    if let Some(practice) = &model.practice {
+        draw_practice(&draw, practice, &model.settings, app.window_rect());
+    }
+
+    if model.take_path.is_some() {
+        draw_recording_indicator(
+            &draw,
+            model.settings.note_color,
+            app.window_rect(),
+            app.time,
+        );
+    }
+
+    draw.to_frame(app, &frame).unwrap();
+    model.egui.draw_to_frame(&frame).unwrap();
+}
+
+fn midi_to_x(midi: f32, model: &Model) -> f32 {
+    let fraction = scale_fraction(
+        midi,
+        model.midi_bounds.low as f32,
+        model.midi_bounds.high as f32,
+        model.settings.x_axis_scale,
+    );
+    map_range(
+        fraction,
+        0.0,
+        1.0,
+        model.line_bounds[0],
+        model.line_bounds[1],
+    )
+}
+
+/// Where `midi` lies between `low` and `high` along the given scale, 0 at `low`
+/// and 1 at `high`.
+fn scale_fraction(midi: f32, low: f32, high: f32, scale: XAxisScale) -> f32 {
+    match scale {
+        XAxisScale::Midi => map_range(midi, low, high, 0.0, 1.0),
+        // the A4 reference scales all three frequencies alike, so it doesn't matter here
+        XAxisScale::LinearHz => map_range(
+            midi_to_freq(midi, DEFAULT_A4),
+            midi_to_freq(low, DEFAULT_A4),
+            midi_to_freq(high, DEFAULT_A4),
+            0.0,
+            1.0,
+        ),
+    }
+}
+
+/// The part of the trail that should be drawn. Points recorded before the first
+/// detection only keep the timeline continuous.
+fn visible_trail(model: &Model) -> &[TrailPoint] {
+    model
+        .locations
+        .get(model.leading_silent_points..)
+        .unwrap_or(&[])
+}
+
+/// The visible trail, preceded by the points of the current take or session that
+/// scrolled off its start.
+fn recorded_trail(model: &Model) -> Vec<TrailPoint> {
+    let mut trail = model.scrolled_off_points.clone();
+    trail.extend_from_slice(visible_trail(model));
+    trail
+}
+
+fn point_color(point: &TrailPoint, model: &Model) -> LinSrgb {
+    let color = base_point_color(point, model);
+    let settings = &model.settings;
+    if !settings.clarity_shading {
+        return color;
+    }
+    // mix towards gray of the same luminance, so unclear points stay as bright
+    let luminance = 0.2126 * color.red + 0.7152 * color.green + 0.0722 * color.blue;
+    let gray = lin_srgb(luminance, luminance, luminance);
+    // detections below the clarity threshold are dropped, so spread the range above it
+    let unclarity = (1.0 - point.clarity) / (1.0 - settings.clarity_threshold).max(0.01);
+    let desaturation = settings.clarity_shading_strength * unclarity.clamp(0.0, 1.0);
+    color.mix(&gray, desaturation)
+}
+
+fn base_point_color(point: &TrailPoint, model: &Model) -> LinSrgb {
+    let settings = &model.settings;
+    let mix_factor = match settings.color_mode {
+        ColorMode::Position => map_range(
+            point.pos.x,
+            model.line_bounds[0],
+            model.line_bounds[1],
+            0.0,
+            1.0,
+        ),
+        ColorMode::AbsolutePitch => {
+            // map over a fixed range so a pitch keeps its color regardless of the bounds
+            let reference = MidiBounds::default();
+            scale_fraction(
+                point.midi,
+                reference.low as f32,
+                reference.high as f32,
+                settings.x_axis_scale,
+            )
+        }
+        ColorMode::PitchClass => {
+            let pitch_class = point.midi.round().rem_euclid(12.0) as usize;
+            return settings.pitch_colors[pitch_class];
+        }
+    };
+    if settings.mirror_x {
+        gradient_color(settings, 1.0 - mix_factor)
+    } else {
+        gradient_color(settings, mix_factor)
+    }
+}
+
+/// Color along the left -> center -> right gradient, `mix_factor` in 0..=1.
+fn gradient_color(settings: &Settings, mix_factor: f32) -> LinSrgb {
+    if mix_factor < 0.5 {
+        settings
+            .left_color
+            .mix(&settings.center_color, mix_factor * 2.0)
+    } else {
+        settings
+            .center_color
+            .mix(&settings.right_color, mix_factor * 2.0 - 1.0)
     }
+}
 
-    let left_color = model.settings.left_color;
-    let right_color = model.settings.right_color;
+fn draw_trail(draw: &Draw, model: &Model, window: Rect) {
+    let weight = 10.0 * model.current_level + 1.0;
+    let trail = visible_trail(model);
 
-    let points_iter = model.locations.iter().map(|point| {
-        let screen_pos = from_camera_view(*point, model);
-        let mix_factor = map_range(point.x, -8.0, 8.0, 0.0, 1.0);
-        let color = left_color.mix(&right_color, mix_factor);
-        (screen_pos, color)
+    if model.settings.visualization_mode == VisualizationMode::Circular {
+        draw_clock_face(draw);
+    }
+
+    if model.settings.show_ghost && !model.ghost.is_empty() {
+        draw_ghost(draw, model, trail);
+    }
+
+    if let (Some(interval), VisualizationMode::Spiral) = (
+        model.settings.grid_interval,
+        model.settings.visualization_mode,
+    ) {
+        draw_time_grid(draw, model, trail, interval);
+    }
+
+    let points_iter = trail.iter().map(|point| {
+        let screen_pos = screen_position(point, model);
+        (screen_pos, point_color(point, model))
     });
 
-    draw.polyline()
-        .weight(10.0 * model.current_level + 1.0)
-        .points_colored(points_iter);
+    let depth_shading = model.settings.depth_shading;
+    let line_style = model.settings.line_style;
+    if depth_shading || line_style == LineStyle::Mesh {
+        let points: Vec<(Vec2, LinSrgb, f32)> = trail
+            .iter()
+            .zip(points_iter)
+            .map(|(point, (screen_pos, color))| {
+                if !depth_shading {
+                    return (screen_pos, color, weight);
+                }
+                let depth = depth_factor(point.pos, model);
+                let color = lin_srgb(color.red * depth, color.green * depth, color.blue * depth);
+                (screen_pos, color, weight * depth)
+            })
+            .collect();
+
+        if line_style == LineStyle::Mesh {
+            draw_mesh_line(draw, &points);
+        } else {
+            // a polyline has a single weight, so draw each segment separately
+            for pair in points.windows(2) {
+                let (start, _, _) = pair[0];
+                let (end, color, weight) = pair[1];
+                draw.line()
+                    .start(start)
+                    .end(end)
+                    .weight(weight)
+                    .color(color);
+            }
+        }
+    } else if trail.len() > 1 {
+        draw.polyline().weight(weight).points_colored(points_iter);
+    }
+
+    if !model.chord_points.is_empty() {
+        draw_chord_points(draw, model, weight);
+    }
 
     // soft clear screen
+    let background = model.settings.background_color;
+    draw.rect().xy(window.xy()).wh(window.wh()).color(lin_srgba(
+        background.red,
+        background.green,
+        background.blue,
+        model.clear_alpha,
+    ));
+
+    if model.has_pitch && !trail.is_empty() {
+        draw_note(draw, model, window);
+    }
+}
+
+/// Draws a line of varying width and color as a triangle strip. Joins are
+/// mitered, so thick lines keep their width around sharp bends without gaps.
+fn draw_mesh_line(draw: &Draw, points: &[(Vec2, LinSrgb, f32)]) {
+    // joins are at most this many times wider than the line, sharp spikes get cut off
+    const MITER_LIMIT: f32 = 4.0;
+    if points.len() < 2 {
+        return;
+    }
+
+    let mut vertices = Vec::with_capacity(points.len() * 2);
+    for (i, &(pos, color, width)) in points.iter().enumerate() {
+        let previous = points[i.saturating_sub(1)].0;
+        let next = points[(i + 1).min(points.len() - 1)].0;
+        let incoming = (pos - previous).normalize_or_zero();
+        let outgoing = (next - pos).normalize_or_zero();
+        let segment = if incoming == Vec2::ZERO {
+            outgoing
+        } else {
+            incoming
+        };
+        let mut tangent = (incoming + outgoing).normalize_or_zero();
+        if tangent == Vec2::ZERO {
+            // the line turns back on itself
+            tangent = segment;
+        }
+        let normal = tangent.perp();
+        let miter = 1.0 / normal.dot(segment.perp()).abs().max(1.0 / MITER_LIMIT);
+        let offset = normal * width / 2.0 * miter;
+        vertices.push(((pos + offset).extend(0.0), color));
+        vertices.push(((pos - offset).extend(0.0), color));
+    }
+
+    let indices = (0..points.len() - 1).flat_map(|i| {
+        let left = i * 2;
+        [left, left + 1, left + 2, left + 1, left + 3, left + 2]
+    });
+    draw.mesh().indexed_colored(vertices, indices);
+}
+
+/// Draws a dot for every extra pitch of a chord, next to the main trail.
+fn draw_chord_points(draw: &Draw, model: &Model, weight: f32) {
+    for point in &model.chord_points {
+        let mut color = point_color(point, model);
+        let mut radius = weight / 2.0;
+        if model.settings.depth_shading {
+            let depth = depth_factor(point.pos, model);
+            color = lin_srgb(color.red * depth, color.green * depth, color.blue * depth);
+            radius *= depth;
+        }
+        draw.ellipse()
+            .xy(screen_position(point, model))
+            .radius(radius)
+            .color(color);
+    }
+}
+
+const MAX_SNAPSHOTS: usize = 2;
+
+/// A trail frozen in screen space, see `capture_snapshot`.
+struct Snapshot {
+    label: String,
+    points: Vec<Vec2>,
+}
+
+/// Draws each snapshot in its own color, with its caption in the top left corner.
+fn draw_snapshots(draw: &Draw, snapshots: &[Snapshot], window: Rect) {
+    const COLORS: [(f32, f32, f32); MAX_SNAPSHOTS] = [(0.3, 0.8, 1.0), (1.0, 0.6, 0.2)];
+
+    for (i, (snapshot, (red, green, blue))) in snapshots.iter().zip(COLORS).enumerate() {
+        if snapshot.points.len() > 1 {
+            draw.polyline()
+                .weight(2.0)
+                .points(snapshot.points.iter().copied())
+                .color(rgba(red, green, blue, 0.5));
+        }
+        draw.text(&snapshot.label)
+            .xy(window.top_left() + vec2(120.0, -30.0 - 24.0 * i as f32))
+            .w(200.0)
+            .left_justify()
+            .font_size(18)
+            .color(rgb(red, green, blue));
+    }
+}
+
+/// Where along z the trail was `time` seconds (see `TrailPoint::time`) into it.
+/// Past the head it continues at the current time scale.
+fn z_at_time(trail: &[TrailPoint], time: f32, model: &Model) -> f32 {
+    let index = trail.partition_point(|point| point.time < time);
+    match (index.checked_sub(1).map(|i| &trail[i]), trail.get(index)) {
+        (Some(before), Some(after)) => {
+            let t = (time - before.time) / (after.time - before.time);
+            before.pos.z + t * (after.pos.z - before.pos.z)
+        }
+        (Some(last), None) => last.pos.z + (time - last.time) * z_per_second(model),
+        (None, Some(first)) => first.pos.z - (first.time - time) * z_per_second(model),
+        (None, None) => model.head.pos.z + (time - model.head.time) * z_per_second(model),
+    }
+}
+
+/// Draws the loaded reference take dimmed, on the same timeline as the live trail.
+fn draw_ghost(draw: &Draw, model: &Model, trail: &[TrailPoint]) {
+    let anchor = trail.first().unwrap_or(&model.head);
+    let to_point = |time: f32, midi: u8| {
+        let z = z_at_time(trail, time, model);
+        let midi = midi as f32;
+        TrailPoint {
+            pos: Vec3::new(
+                midi_to_x(midi, model),
+                anchor.pos.y + (z - anchor.pos.z) * Y_PER_SECOND / Z_PER_SECOND,
+                z,
+            ),
+            midi,
+            clarity: 1.0,
+            is_pitched: true,
+            time,
+        }
+    };
+
+    let points = model
+        .ghost
+        .iter()
+        .filter_map(|note| Some((note, note.midi?)))
+        .flat_map(|(note, midi)| {
+            [
+                to_point(note.start, midi),
+                to_point(note.start + note.duration, midi),
+            ]
+        });
+    draw.polyline()
+        .weight(2.0)
+        .points(points.map(|point| screen_position(&point, model)))
+        .color(faded(model.settings.note_color, 0.25));
+}
+
+/// Draws a faint line across the trail every `interval` seconds of trail time.
+fn draw_time_grid(draw: &Draw, model: &Model, trail: &[TrailPoint], interval: f32) {
+    let (first, last) = match (trail.first(), trail.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return,
+    };
+    let mut time = (first.time / interval).ceil() * interval;
+    while time <= last.time {
+        let z = z_at_time(trail, time, model);
+        // the head moves along y and z at a fixed ratio
+        let y = last.pos.y + (z - last.pos.z) * Y_PER_SECOND / Z_PER_SECOND;
+        let start = from_camera_view(Vec3::new(model.line_bounds[0], y, z), model);
+        let end = from_camera_view(Vec3::new(model.line_bounds[1], y, z), model);
+        draw.line()
+            .start(start)
+            .end(end)
+            .weight(1.0)
+            .color(faded(model.settings.note_color, 0.2));
+        time += interval;
+    }
+}
+
+/// Seconds without a detection after which the idle hint comes back
+const IDLE_DELAY: f32 = 15.0;
+
+/// A slowly breathing line in the trail colors with a prompt to start playing.
+fn draw_idle_hint(draw: &Draw, model: &Model, time: f32) {
+    const BREATH_PERIOD: f32 = 4.0;
+    const STEPS: usize = 32;
+    const WIDTH: f32 = 500.0;
+
+    let brightness = 0.35 + 0.25 * (time / BREATH_PERIOD * TAU).sin();
+    let points = (0..=STEPS).map(|i| {
+        let mix_factor = i as f32 / STEPS as f32;
+        let color = gradient_color(&model.settings, mix_factor);
+        let color = lin_srgb(
+            color.red * brightness,
+            color.green * brightness,
+            color.blue * brightness,
+        );
+        (vec2((mix_factor - 0.5) * WIDTH, 0.0), color)
+    });
+    draw.polyline().weight(4.0).points_colored(points);
+    draw.text("play something!")
+        .y(-40.0)
+        .font_size(24)
+        .color(lin_srgb(brightness, brightness, brightness));
+}
+
+fn draw_note(draw: &Draw, model: &Model, window: Rect) {
+    let font_size = model.settings.note_font_size;
+    // keep a corner anchored label fully inside the window
+    let margin = font_size as f32 * 1.5;
+    let pos = match model.settings.note_anchor {
+        NoteAnchor::FollowPoint => match model.locations.last() {
+            Some(last) => vec2(screen_position(last, model).x, 0.0),
+            None => return,
+        },
+        NoteAnchor::Center => Vec2::ZERO,
+        NoteAnchor::TopLeft => window.top_left() + vec2(margin, -margin),
+        NoteAnchor::TopRight => window.top_right() + vec2(-margin, -margin),
+        NoteAnchor::BottomLeft => window.bottom_left() + vec2(margin, margin),
+        NoteAnchor::BottomRight => window.bottom_right() + vec2(-margin, margin),
+    };
+    draw.text(&model.current_note)
+        .color(model.settings.note_color)
+        .xy(pos)
+        .w(margin * 2.0)
+        .font_size(font_size);
+}
+
+fn draw_tuner(draw: &Draw, model: &Model) {
+    const BAR_WIDTH: f32 = 600.0;
+    const IN_TUNE_CENTS: f32 = 5.0;
+
+    if !model.has_pitch {
+        return;
+    }
+
+    let in_tune = model.current_cents.abs() <= IN_TUNE_CENTS;
+    let color = if in_tune {
+        rgb(0.1, 0.9, 0.2).into_linear()
+    } else {
+        model.settings.note_color
+    };
+
+    draw.text(&midi_to_note_name(model.current_midi))
+        .y(120.0)
+        .font_size(128)
+        .color(color);
+    draw.text(&model.current_note)
+        .y(20.0)
+        .font_size(48)
+        .color(GRAY);
+
+    // scale from -50 to +50 cents with a center mark
+    draw.line()
+        .start(pt2(-BAR_WIDTH / 2.0, -80.0))
+        .end(pt2(BAR_WIDTH / 2.0, -80.0))
+        .weight(2.0)
+        .color(GRAY);
+    draw.line()
+        .start(pt2(0.0, -100.0))
+        .end(pt2(0.0, -60.0))
+        .weight(2.0)
+        .color(GRAY);
+
+    // the dead zone and snapping can leave the pitch more than 50 cents away
+    let needle_x = map_range(
+        model.current_cents.clamp(-50.0, 50.0),
+        -50.0,
+        50.0,
+        -BAR_WIDTH / 2.0,
+        BAR_WIDTH / 2.0,
+    );
+    draw.line()
+        .start(pt2(needle_x, -130.0))
+        .end(pt2(needle_x, -30.0))
+        .weight(6.0)
+        .color(color);
+    draw.text(&format!("{:+.0} cents", model.current_cents))
+        .y(-170.0)
+        .font_size(24)
+        .color(color);
+}
+
+/// `color` with the given alpha, for overlays drawn in the theme's note color.
+fn faded(color: LinSrgb, alpha: f32) -> LinSrgba {
+    lin_srgba(color.red, color.green, color.blue, alpha)
+}
+
+fn draw_legend(draw: &Draw, model: &Model, window: Rect) {
+    const STEPS: usize = 64;
+    const WIDTH: f32 = 400.0;
+    const HEIGHT: f32 = 16.0;
+
+    let center = window.mid_bottom() + vec2(0.0, 40.0);
+
+    if model.settings.color_mode == ColorMode::PitchClass {
+        let swatch_width = WIDTH / 12.0;
+        for (pitch_class, color) in model.settings.pitch_colors.iter().enumerate() {
+            let x = center.x - WIDTH / 2.0 + (pitch_class as f32 + 0.5) * swatch_width;
+            draw.rect()
+                .x_y(x, center.y)
+                .w_h(swatch_width, HEIGHT)
+                .color(*color);
+            draw.text(pitch_class_name(pitch_class as u8))
+                .x_y(x, center.y + HEIGHT)
+                .font_size(12)
+                .color(model.settings.note_color);
+        }
+        return;
+    }
+
+    let step_width = WIDTH / STEPS as f32;
+    for i in 0..STEPS {
+        let mix_factor = (i as f32 + 0.5) / STEPS as f32;
+        let color = gradient_color(&model.settings, mix_factor);
+        let x = center.x - WIDTH / 2.0 + (i as f32 + 0.5) * step_width;
+        draw.rect()
+            .x_y(x, center.y)
+            .w_h(step_width, HEIGHT)
+            .color(color);
+    }
+
+    let (low, high) = if model.settings.color_mode == ColorMode::AbsolutePitch {
+        let reference = MidiBounds::default();
+        (reference.low, reference.high)
+    } else {
+        (model.midi_bounds.low, model.midi_bounds.high)
+    };
+    let (left, right) = if model.settings.mirror_x {
+        (high, low)
+    } else {
+        (low, high)
+    };
+    let left = midi_to_note_name(left);
+    let right = midi_to_note_name(right);
+    draw.text(&left)
+        .x_y(center.x - WIDTH / 2.0 - 30.0, center.y)
+        .font_size(16)
+        .color(model.settings.note_color);
+    draw.text(&right)
+        .x_y(center.x + WIDTH / 2.0 + 30.0, center.y)
+        .font_size(16)
+        .color(model.settings.note_color);
+}
+
+fn draw_histogram(draw: &Draw, note_counts: &[u32; 12], color: LinSrgb, window: Rect) {
+    const BAR_WIDTH: f32 = 24.0;
+    const MAX_HEIGHT: f32 = 150.0;
+
+    let max_count = note_counts.iter().copied().max().unwrap_or(0).max(1);
+    let origin = window.bottom_left() + vec2(40.0, 40.0);
+    for (pitch_class, &count) in note_counts.iter().enumerate() {
+        let height = MAX_HEIGHT * count as f32 / max_count as f32;
+        let x = origin.x + pitch_class as f32 * (BAR_WIDTH + 4.0);
+        draw.rect()
+            .x_y(x, origin.y + height / 2.0)
+            .w_h(BAR_WIDTH, height)
+            .color(faded(color, 0.6));
+
+        draw.text(pitch_class_name(pitch_class as u8))
+            .x_y(x, origin.y - 14.0)
+            .font_size(12)
+            .color(color);
+    }
+}
+
+/// Draws the spectrogram in the bottom right corner, with time running left to right
+/// and frequency upwards.
+fn draw_spectrogram(draw: &Draw, spectrogram: &Spectrogram, text_color: LinSrgb, window: Rect) {
+    const WIDTH: f32 = 400.0;
+    const HEIGHT: f32 = 150.0;
+
+    let origin = window.bottom_right() + vec2(-40.0 - WIDTH, 40.0);
     draw.rect()
-        .w_h(2000.0, 2000.0)
-        .color(srgba(0.0, 0.0, 0.0, 0.15));
+        .xy(origin + vec2(WIDTH, HEIGHT) / 2.0)
+        .w_h(WIDTH, HEIGHT)
+        .color(BLACK);
+
+    let columns = &spectrogram.columns;
+    let rows = match columns.front() {
+        Some(column) => column.levels.len(),
+        None => return,
+    };
+    let column_width = WIDTH / spectrogram::COLUMNS as f32;
+    let row_height = HEIGHT * spectrogram.bin_width / spectrogram::MAX_FREQUENCY;
+    // new columns enter on the right
+    let first_x = origin.x + WIDTH - columns.len() as f32 * column_width;
+
+    let vertices = columns.iter().enumerate().flat_map(|(i, column)| {
+        let x = first_x + (i as f32 + 0.5) * column_width;
+        column.levels.iter().enumerate().map(move |(row, &level)| {
+            let pos = vec3(x, origin.y + row as f32 * row_height, 0.0);
+            (pos, lin_srgb(level, level.powi(2), level.powi(4)))
+        })
+    });
+    let indices = (0..columns.len().saturating_sub(1)).flat_map(|i| {
+        (0..rows - 1).flat_map(move |row| {
+            let bottom_left = i * rows + row;
+            let bottom_right = bottom_left + rows;
+            [
+                bottom_left,
+                bottom_right,
+                bottom_left + 1,
+                bottom_right,
+                bottom_right + 1,
+                bottom_left + 1,
+            ]
+        })
+    });
+    draw.mesh().indexed_colored(vertices, indices);
 
-    let text_pos = from_camera_view(*model.locations.last().unwrap_or(&Vec3::ZERO), model);
-    if model.is_running {
-        draw.text(&model.current_note).x(text_pos.x).font_size(32);
+    for (i, column) in columns.iter().enumerate() {
+        if let Some(frequency) = column.fundamental {
+            if frequency <= spectrogram::MAX_FREQUENCY {
+                let y = origin.y + HEIGHT * frequency / spectrogram::MAX_FREQUENCY;
+                let x = first_x + (i as f32 + 0.5) * column_width;
+                draw.rect()
+                    .x_y(x, y)
+                    .w_h(column_width, 2.0)
+                    .color(rgb(0.1, 0.9, 0.9));
+            }
+        }
     }
 
-    draw.to_frame(app, &frame).unwrap();
-    model.egui.draw_to_frame(&frame).unwrap();
+    draw.text("spectrogram")
+        .xy(origin + vec2(WIDTH / 2.0, HEIGHT + 10.0))
+        .font_size(12)
+        .color(text_color);
+}
+
+/// A blinking dot in the top right corner while a take is recorded.
+fn draw_recording_indicator(draw: &Draw, text_color: LinSrgb, window: Rect, time: f32) {
+    const BLINK_PERIOD: f32 = 1.0;
+    let pos = window.top_right() - vec2(60.0, 30.0);
+    if (time / BLINK_PERIOD).fract() < 0.5 {
+        draw.ellipse().xy(pos).radius(8.0).color(rgb(0.9, 0.1, 0.1));
+    }
+    draw.text("REC")
+        .xy(pos + vec2(30.0, 0.0))
+        .font_size(16)
+        .color(text_color);
+}
+
+fn draw_practice(draw: &Draw, practice: &Practice, settings: &Settings, window: Rect) {
+    let pos = window.mid_top() - vec2(0.0, 60.0);
+    let score = format!("{} / {}", practice.hits, practice.hits + practice.misses);
+    draw.text(&score)
+        .xy(pos - vec2(0.0, 50.0))
+        .font_size(24)
+        .color(settings.note_color);
+
+    if let Some(target) = practice.current_target() {
+        let color = if practice.is_matching {
+            rgb(0.1, 0.9, 0.2)
+        } else {
+            rgb(0.9, 0.1, 0.1)
+        };
+        draw.text(&settings.notation.format(&target.tab))
+            .xy(pos)
+            .font_size(48)
+            .color(color);
+    }
 }
 
 struct InputModel {
@@ -355,8 +3097,146 @@ fn pass_in(model: &mut InputModel, buffer: &Buffer) {
     }
 }
 
-fn freq_to_midi(freq: f32) -> u8 {
-    (12.0 * (freq / 440.0).log2() + 69.0).round() as u8
+/// Alpha of the soft clear rect for a frame lasting `delta` seconds, chosen so
+/// the trail fades to 1% brightness after `fade_time` seconds at any frame rate.
+fn calc_clear_alpha(delta: f32, fade_time: f32) -> f32 {
+    const REMAINING: f32 = 0.01;
+    (1.0 - REMAINING.powf(delta / fade_time)).clamp(0.0, 1.0)
+}
+
+/// Per-sample multiplier for the peak level, chosen so a held peak falls to 1%
+/// after `decay_time` seconds.
+fn calc_level_decay(decay_time: f32) -> f32 {
+    const REMAINING: f32 = 0.01;
+    REMAINING.powf(1.0 / (SAMPLE_RATE as f32 * decay_time))
+}
+
+/// Runs monophonic detection on a block of `SIZE` samples, independent of the
+/// live input so batch processing gets the same results.
+fn detect_pitch(
+    signal: &[f32],
+    sample_rate: usize,
+    power_threshold: f32,
+    clarity_threshold: f32,
+) -> Option<poly::Pitch> {
+    let mut detector = McLeodDetector::new(SIZE, PADDING);
+    detector
+        .get_pitch(signal, sample_rate, power_threshold, clarity_threshold)
+        .map(|pitch| poly::Pitch {
+            frequency: pitch.frequency,
+            clarity: pitch.clarity,
+        })
+}
+
+/// Power (sum of squares) of a `SIZE` sample window of a sine with peak `level`,
+/// the measure `McLeodDetector` compares its power threshold against.
+fn level_to_power(level: f32) -> f32 {
+    level * level * SIZE as f32 / 2.0
+}
+
+/// Soft limits samples into -1..1 so heavily driven input keeps a rounded
+/// waveform. Only used on the copy of the buffer that goes to the detector.
+fn soft_clip(samples: &mut [f32]) {
+    for sample in samples.iter_mut() {
+        *sample = sample.tanh();
+    }
+}
+
+/// Tapers the block towards its edges, see `WindowFunction`.
+fn apply_window(samples: &mut [f32], window: WindowFunction) {
+    // both are raised cosines, a0 - (1 - a0) * cos(...)
+    let a0 = match window {
+        WindowFunction::Rectangular => return,
+        WindowFunction::Hann => 0.5,
+        WindowFunction::Hamming => 0.54,
+    };
+    let last = (samples.len() - 1).max(1) as f32;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        *sample *= a0 - (1.0 - a0) * (TAU * i as f32 / last).cos();
+    }
+}
+
+/// Whether `midi` is about an octave above a stable run of recent detections,
+/// which usually means the detector locked onto the second harmonic.
+fn is_octave_above(midi: f32, recent_midi: &VecDeque<f32>) -> bool {
+    const TOLERANCE: f32 = 1.5;
+    const MAX_SPREAD: f32 = 7.0;
+
+    if recent_midi.len() < RECENT_MIDI_LEN {
+        return false;
+    }
+    let (min, max) = recent_midi
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), &m| {
+            (min.min(m), max.max(m))
+        });
+    if max - min > MAX_SPREAD {
+        return false;
+    }
+    let average = recent_midi.iter().sum::<f32>() / recent_midi.len() as f32;
+    (midi - average - 12.0).abs() <= TOLERANCE
+}
+
+/// Rounds `midi` to the nearest note, but keeps `displayed` until the pitch is
+/// more than `margin_cents` past the midpoint to a neighbouring note.
+fn apply_dead_zone(midi: f32, displayed: Option<u8>, margin_cents: f32) -> u8 {
+    if let Some(displayed) = displayed {
+        if (midi - displayed as f32).abs() < 0.5 + margin_cents / 100.0 {
+            return displayed;
+        }
+    }
+    midi.round() as u8
+}
+
+fn freq_to_midi(freq: f32, a4: f32) -> u8 {
+    freq_to_midi_float(freq, a4).round() as u8
+}
+
+fn pitch_class_name(pitch_class: u8) -> &'static str {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    NAMES[pitch_class as usize % 12]
+}
+
+/// Detections that have to be counted before a position is guessed
+const MIN_POSITION_DETECTIONS: u32 = 50;
+
+/// Guesses the harmonica position (1 = playing in the harp's key) and its tonic
+/// pitch class from the note histogram. The tonic is taken to be the pitch class
+/// that, together with its fifth and third, was played the most.
+fn infer_position(note_counts: &[u32; 12], key: &str) -> Option<(u8, u8)> {
+    if note_counts.iter().sum::<u32>() < MIN_POSITION_DETECTIONS {
+        return None;
+    }
+    let count = |pitch_class: usize| note_counts[pitch_class % 12] as f32;
+    let tonic = (0..12)
+        .map(|tonic| {
+            let thirds = count(tonic + 3) + count(tonic + 4);
+            (tonic, count(tonic) + 0.5 * count(tonic + 7) + 0.25 * thirds)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?
+        .0 as u8;
+
+    let harp_key = get_harmonica_key_semitone_offset(key).rem_euclid(12) as u8;
+    let interval = (tonic + 12 - harp_key) % 12;
+    // each position is a fifth above the previous one, and 7 * 7 = 1 (mod 12)
+    let position = interval * 7 % 12 + 1;
+    Some((position, tonic))
+}
+
+fn position_name(position: u8) -> String {
+    match position {
+        1 => "1st (straight harp)".to_owned(),
+        2 => "2nd (cross harp)".to_owned(),
+        3 => "3rd (slant harp)".to_owned(),
+        _ => format!("{}th", position),
+    }
+}
+
+fn midi_to_note_name(midi: u8) -> String {
+    let octave = midi as i32 / 12 - 1;
+    format!("{}{}", pitch_class_name(midi), octave)
 }
 
 fn calc_freq_bounds(key: &str) -> MidiBounds {
@@ -369,8 +3249,12 @@ fn calc_freq_bounds(key: &str) -> MidiBounds {
     }
 }
 
-fn freq_to_midi_float(freq: f32) -> f32 {
-    12.0 * (freq / 440.0).log2() + 69.0
+fn midi_to_freq(midi: f32, a4: f32) -> f32 {
+    a4 * 2.0_f32.powf((midi - 69.0) / 12.0)
+}
+
+fn freq_to_midi_float(freq: f32, a4: f32) -> f32 {
+    12.0 * (freq / a4).log2() + 69.0
 }
 
 fn get_harmonica_key_semitone_offset(key: &str) -> i8 {
@@ -397,6 +3281,39 @@ fn get_harmonica_key_semitone_offset(key: &str) -> i8 {
     }
 }
 
+fn get_tuning_default_key(tuning: &str) -> &'static str {
+    match tuning {
+        "country" => "G",
+        "melody maker" => "A",
+        "natural minor" => "D",
+        "harmonic minor" => "A",
+        "paddy richter" => "D",
+        _ => "C",
+    }
+}
+
+/// Resolves `midi_to_tab` for every possible MIDI value up front, so looking up
+/// a detected note is a plain index.
+fn build_tab_table(key: &str, notes_in_order: &[String], notation: NotationStyle) -> Vec<String> {
+    (0..=u8::MAX)
+        .map(|midi| notation.format(&midi_to_tab(midi, key, notes_in_order)))
+        .collect()
+}
+
+/// Finds the MIDI note closest to `midi` that has a tab in the current key and tuning.
+fn nearest_playable_note(midi: f32, tab_table: &[String]) -> Option<u8> {
+    tab_table
+        .iter()
+        .enumerate()
+        .filter(|(_, tab)| !tab.is_empty())
+        .map(|(note, _)| note as u8)
+        .min_by(|a, b| {
+            let distance_a = (*a as f32 - midi).abs();
+            let distance_b = (*b as f32 - midi).abs();
+            distance_a.total_cmp(&distance_b)
+        })
+}
+
 fn midi_to_tab(midi: u8, key: &str, notes_in_order: &[String]) -> String {
     let offset = get_harmonica_key_semitone_offset(key);
     let index: isize = midi as isize - 60 - offset as isize;
@@ -412,7 +3329,143 @@ fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event:
 }
 
 fn key_pressed(_app: &App, model: &mut Model, key: Key) {
-    if key == Key::F1 {
-        model.ui_visible = !model.ui_visible;
+    if model.egui.ctx().wants_keyboard_input() {
+        // typing in a text field
+        return;
+    }
+    if model.settings.keyboard_input {
+        // note keys take precedence over bindings on the same key
+        match key {
+            Key::Z => {
+                model.keyboard_base = model.keyboard_base.saturating_sub(12).max(12);
+                return;
+            }
+            Key::X => {
+                model.keyboard_base = (model.keyboard_base + 12).min(108);
+                return;
+            }
+            _ => {
+                if let Some(semitone) = piano_key_semitone(key) {
+                    model.keyboard_midi = Some(model.keyboard_base + semitone);
+                    return;
+                }
+            }
+        }
+    }
+    if let Some(action) = keybindings::action_for(&model.settings.keybindings, key) {
+        model.pending_actions.push(action);
+    }
+}
+
+fn key_released(_app: &App, model: &mut Model, key: Key) {
+    if let Some(semitone) = piano_key_semitone(key) {
+        // another key may have been pressed since
+        if model.keyboard_midi == Some(model.keyboard_base + semitone) {
+            model.keyboard_midi = None;
+        }
+    }
+}
+
+/// Semitones above `keyboard_base` for a key laid out like a piano, with the
+/// home row as white keys and the row above as black keys.
+fn piano_key_semitone(key: Key) -> Option<u8> {
+    let semitone = match key {
+        Key::A => 0,
+        Key::W => 1,
+        Key::S => 2,
+        Key::E => 3,
+        Key::D => 4,
+        Key::F => 5,
+        Key::T => 6,
+        Key::G => 7,
+        Key::Y => 8,
+        Key::H => 9,
+        Key::U => 10,
+        Key::J => 11,
+        Key::K => 12,
+        _ => return None,
+    };
+    Some(semitone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubled_pitch_after_stable_notes_is_octave_above() {
+        let recent_midi: VecDeque<f32> = [60.0, 60.2, 59.8, 60.1, 60.0, 59.9, 60.0, 60.1]
+            .into_iter()
+            .collect();
+        let doubled = freq_to_midi_float(2.0 * midi_to_freq(60.0, DEFAULT_A4), DEFAULT_A4);
+        assert!(is_octave_above(doubled, &recent_midi));
+        assert!(!is_octave_above(62.0, &recent_midi));
+
+        // not enough context yet
+        let short: VecDeque<f32> = recent_midi.iter().copied().take(4).collect();
+        assert!(!is_octave_above(doubled, &short));
+
+        // a melody jumping around isn't a stable run
+        let mut jumping = recent_midi.clone();
+        jumping[3] = 70.0;
+        assert!(!is_octave_above(doubled, &jumping));
+    }
+
+    #[test]
+    fn a4_reference_moves_the_note_grid() {
+        assert_eq!(freq_to_midi_float(442.0, 442.0), 69.0);
+        assert_eq!(freq_to_midi(442.0, 442.0), 69);
+        // 440 Hz is a few cents flat of A4 at a 442 reference
+        assert!(freq_to_midi_float(440.0, 442.0) < 69.0);
+        // more than a quarter tone above A4 at 440, less at 450
+        assert_eq!(freq_to_midi(455.0, 440.0), 70);
+        assert_eq!(freq_to_midi(455.0, 450.0), 69);
+    }
+
+    #[test]
+    fn clipped_sine_keeps_its_fundamental() {
+        const FREQUENCY: f32 = 220.0;
+        // driven far past full scale by a hot input
+        let mut signal: Vec<f32> = (0..SIZE)
+            .map(|i| {
+                let phase = TAU * FREQUENCY * i as f32 / SAMPLE_RATE as f32;
+                (4.0 * phase.sin()).clamp(-1.0, 1.0)
+            })
+            .collect();
+        soft_clip(&mut signal);
+        assert!(signal.iter().all(|sample| sample.abs() < 1.0));
+
+        let pitch = detect_pitch(
+            &signal,
+            SAMPLE_RATE,
+            DEFAULT_POWER_THRESHOLD,
+            DEFAULT_CLARITY_THRESHOLD,
+        )
+        .expect("no pitch detected");
+        assert_eq!(
+            freq_to_midi(pitch.frequency, DEFAULT_A4),
+            freq_to_midi(FREQUENCY, DEFAULT_A4)
+        );
+    }
+
+    #[test]
+    fn tab_table_matches_midi_to_tab() {
+        for tuning in TUNINGS {
+            let notes_in_order = harptabber::tuning_to_notes_in_order(tuning).0;
+            for key in KEYS {
+                let table = build_tab_table(key, &notes_in_order, NotationStyle::Native);
+                assert_eq!(table.len(), u8::MAX as usize + 1);
+                for midi in 0..=u8::MAX {
+                    assert_eq!(
+                        table[midi as usize],
+                        midi_to_tab(midi, key, &notes_in_order),
+                        "{} {} harp, MIDI {}",
+                        key,
+                        tuning,
+                        midi
+                    );
+                }
+            }
+        }
     }
 }
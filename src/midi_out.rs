@@ -0,0 +1,94 @@
+use midir::{MidiOutput as MidirOutput, MidiOutputConnection};
+
+const CLIENT_NAME: &str = "tab-visualizer";
+const NOTE_ON: u8 = 0x90;
+const NOTE_OFF: u8 = 0x80;
+/// Number of consecutive blocks a new note (or silence) must be detected
+/// before it replaces the sounding note.
+const STABLE_BLOCKS: usize = 3;
+
+/// Sends note-on/note-off messages for detected notes to a MIDI port.
+pub struct MidiOutput {
+    connection: MidiOutputConnection,
+    sounding: Option<u8>,
+    candidate: Option<u8>,
+    candidate_count: usize,
+}
+
+pub fn list_ports() -> Vec<String> {
+    let output = match MidirOutput::new(CLIENT_NAME) {
+        Ok(output) => output,
+        Err(_) => return Vec::new(),
+    };
+    output
+        .ports()
+        .iter()
+        .filter_map(|port| output.port_name(port).ok())
+        .collect()
+}
+
+impl MidiOutput {
+    pub fn connect(port_name: &str) -> Result<Self, String> {
+        let output = MidirOutput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| output.port_name(port).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| format!("no MIDI port named {}", port_name))?;
+        let connection = output
+            .connect(&port, CLIENT_NAME)
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            connection,
+            sounding: None,
+            candidate: None,
+            candidate_count: 0,
+        })
+    }
+
+    /// Feeds one block's detection (`None` for silence). The sounding note only
+    /// changes once the new value has been stable for a few blocks, so wavering
+    /// or held notes don't retrigger.
+    pub fn update(&mut self, detected: Option<u8>, velocity: u8) {
+        if detected == self.sounding {
+            self.candidate = None;
+            self.candidate_count = 0;
+            return;
+        }
+
+        if detected == self.candidate {
+            self.candidate_count += 1;
+        } else {
+            self.candidate = detected;
+            self.candidate_count = 1;
+        }
+
+        if self.candidate_count >= STABLE_BLOCKS {
+            self.release();
+            if let Some(note) = detected {
+                self.send(&[NOTE_ON, note, velocity.clamp(1, 127)]);
+                self.sounding = Some(note);
+            }
+            self.candidate = None;
+            self.candidate_count = 0;
+        }
+    }
+
+    fn release(&mut self) {
+        if let Some(note) = self.sounding.take() {
+            self.send(&[NOTE_OFF, note, 0]);
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        if let Err(e) = self.connection.send(message) {
+            println!("failed to send MIDI message: {}", e);
+        }
+    }
+}
+
+impl Drop for MidiOutput {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
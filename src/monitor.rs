@@ -0,0 +1,41 @@
+use nannou_audio::Buffer;
+use std::f32::consts::{PI, TAU};
+
+/// State of the monitor tone, owned by the audio output thread.
+pub struct MonitorModel {
+    pub frequency: f32,
+    pub target_amplitude: f32,
+    /// 0 is fully left, 1 fully right
+    pub pan: f32,
+    amplitude: f32,
+    phase: f32,
+}
+
+impl Default for MonitorModel {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            target_amplitude: 0.0,
+            pan: 0.5,
+            amplitude: 0.0,
+            phase: 0.0,
+        }
+    }
+}
+
+/// Renders a sine at the detected pitch into a stereo buffer, using equal power panning.
+pub fn render(model: &mut MonitorModel, buffer: &mut Buffer) {
+    // the amplitude glides towards its target so pitch updates don't click
+    const SMOOTHING: f32 = 0.001;
+
+    let sample_rate = buffer.sample_rate() as f32;
+    let angle = model.pan.clamp(0.0, 1.0) * PI / 2.0;
+    let (left, right) = (angle.cos(), angle.sin());
+    for frame in buffer.frames_mut() {
+        model.amplitude += SMOOTHING * (model.target_amplitude - model.amplitude);
+        let sample = model.amplitude * (model.phase * TAU).sin();
+        model.phase = (model.phase + model.frequency / sample_rate).fract();
+        frame[0] = sample * left;
+        frame[1] = sample * right;
+    }
+}
@@ -0,0 +1,30 @@
+use nannou_osc as osc;
+
+pub const DETECTION_ADDR: &str = "/tab-visualizer/detection";
+
+/// Broadcasts detections over UDP. Sending never waits for a receiver, so it
+/// can be called from the update loop.
+pub struct OscOutput {
+    sender: osc::Sender<osc::Connected>,
+}
+
+impl OscOutput {
+    pub fn connect(host: &str, port: u16) -> Result<Self, String> {
+        let sender = osc::sender()
+            .and_then(|sender| sender.connect((host, port)))
+            .map_err(|e| e.to_string())?;
+        Ok(Self { sender })
+    }
+
+    /// Sends frequency, MIDI note, clarity and tab as a single message.
+    pub fn send_detection(&self, frequency: f32, midi: u8, clarity: f32, tab: &str) {
+        let args = vec![
+            osc::Type::Float(frequency),
+            osc::Type::Int(midi as i32),
+            osc::Type::Float(clarity),
+            osc::Type::String(tab.to_owned()),
+        ];
+        // a dropped packet is not worth interrupting the visualization for
+        self.sender.send((DETECTION_ADDR, args)).ok();
+    }
+}
@@ -0,0 +1,45 @@
+use nannou::color::{ConvertFrom, LinSrgb};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const PITCH_COLORS_PATH: &str = "pitch_class_colors.txt";
+
+/// One color per pitch class, starting at C.
+pub type PitchColors = [LinSrgb; 12];
+
+pub fn default_rainbow() -> PitchColors {
+    let mut colors = [LinSrgb::new(0.0, 0.0, 0.0); 12];
+    for (pitch_class, color) in colors.iter_mut().enumerate() {
+        let hsv = nannou::color::hsv(pitch_class as f32 / 12.0, 1.0, 1.0);
+        *color = LinSrgb::convert_from(hsv);
+    }
+    colors
+}
+
+/// Reads colors saved by `save`, one `r g b` line per pitch class.
+pub fn load(path: impl AsRef<Path>) -> Option<PitchColors> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut colors = default_rainbow();
+    let mut lines = contents.lines();
+    for color in colors.iter_mut() {
+        let components: Vec<f32> = lines
+            .next()?
+            .split_whitespace()
+            .map(|c| c.parse().ok())
+            .collect::<Option<_>>()?;
+        if components.len() != 3 {
+            return None;
+        }
+        *color = LinSrgb::new(components[0], components[1], components[2]);
+    }
+    Some(colors)
+}
+
+pub fn save(path: impl AsRef<Path>, colors: &PitchColors) -> io::Result<()> {
+    let contents: String = colors
+        .iter()
+        .map(|c| format!("{} {} {}\n", c.red, c.green, c.blue))
+        .collect();
+    fs::write(path, contents)
+}
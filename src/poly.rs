@@ -0,0 +1,119 @@
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// A detected pitch. For polyphonic detection `clarity` is the peak's magnitude
+/// relative to the strongest peak in the block.
+#[derive(Clone, Copy)]
+pub struct Pitch {
+    pub frequency: f32,
+    pub clarity: f32,
+}
+
+/// The signal is zero padded to this many times its length, so peaks can be
+/// located more finely than the bin width of the unpadded block.
+const ZERO_PADDING: usize = 4;
+const MIN_FREQUENCY: f32 = 80.0;
+const MAX_FREQUENCY: f32 = 4000.0;
+/// Peaks weaker than this fraction of the strongest peak are ignored
+const RELATIVE_THRESHOLD: f32 = 0.1;
+/// How far off (relative) a peak may be from a whole multiple of a lower pitch
+/// and still count as its harmonic, a bit more than a quarter tone
+const HARMONIC_TOLERANCE: f32 = 0.03;
+
+/// Finds several simultaneous pitches by picking peaks from the magnitude spectrum.
+pub struct PolyDetector {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl PolyDetector {
+    pub fn new(size: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(size * ZERO_PADDING);
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+            .collect();
+        Self {
+            fft,
+            window,
+            spectrum: vec![Complex::default(); size * ZERO_PADDING],
+        }
+    }
+
+    /// Returns up to `max_pitches` pitches, strongest first. Peaks at a whole
+    /// multiple of a lower peak are treated as its harmonics and dropped, so an
+    /// octave within a chord is not reported separately.
+    pub fn detect(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        max_pitches: usize,
+    ) -> Vec<Pitch> {
+        let power: f32 = signal.iter().map(|sample| sample * sample).sum();
+        if power < power_threshold {
+            return Vec::new();
+        }
+
+        for (i, bin) in self.spectrum.iter_mut().enumerate() {
+            let sample = match (signal.get(i), self.window.get(i)) {
+                (Some(sample), Some(weight)) => sample * weight,
+                _ => 0.0,
+            };
+            *bin = Complex::new(sample, 0.0);
+        }
+        self.fft.process(&mut self.spectrum);
+
+        let bin_width = sample_rate as f32 / self.spectrum.len() as f32;
+        let magnitudes: Vec<f32> = self.spectrum[..self.spectrum.len() / 2]
+            .iter()
+            .map(|bin| bin.norm())
+            .collect();
+        let min_bin = ((MIN_FREQUENCY / bin_width) as usize).max(1);
+        let max_bin = ((MAX_FREQUENCY / bin_width) as usize).min(magnitudes.len() - 1);
+        let strongest = magnitudes[min_bin..max_bin]
+            .iter()
+            .fold(0.0_f32, |max, &magnitude| max.max(magnitude));
+        if strongest <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut peaks = Vec::new();
+        for i in min_bin..max_bin {
+            let (before, magnitude, after) = (magnitudes[i - 1], magnitudes[i], magnitudes[i + 1]);
+            if magnitude > before
+                && magnitude >= after
+                && magnitude >= strongest * RELATIVE_THRESHOLD
+            {
+                // fit a parabola through the peak and its neighbours to find its actual position
+                let offset = 0.5 * (before - after) / (before - 2.0 * magnitude + after);
+                peaks.push(Pitch {
+                    frequency: (i as f32 + offset) * bin_width,
+                    clarity: magnitude / strongest,
+                });
+            }
+        }
+
+        // peaks are in ascending frequency, so fundamentals are kept before their harmonics
+        let mut pitches: Vec<Pitch> = Vec::new();
+        for peak in peaks {
+            if !pitches
+                .iter()
+                .any(|pitch| is_harmonic(peak.frequency, pitch.frequency))
+            {
+                pitches.push(peak);
+            }
+        }
+        pitches.sort_by(|a, b| b.clarity.total_cmp(&a.clarity));
+        pitches.truncate(max_pitches);
+        pitches
+    }
+}
+
+fn is_harmonic(frequency: f32, fundamental: f32) -> bool {
+    let ratio = frequency / fundamental;
+    let harmonic = ratio.round();
+    harmonic >= 2.0 && (ratio / harmonic - 1.0).abs() < HARMONIC_TOLERANCE
+}
@@ -0,0 +1,126 @@
+use crate::native_tab;
+use std::fs;
+use std::path::Path;
+
+/// A note the player is expected to play, held for `duration` seconds.
+pub struct TargetNote {
+    /// In harptabber's notation
+    pub tab: String,
+    pub duration: f32,
+}
+
+/// Steps through a target melody on a timer and scores the detected notes against it.
+pub struct Practice {
+    notes: Vec<TargetNote>,
+    index: usize,
+    elapsed: f32,
+    matched_time: f32,
+    /// Set when a target repeats the previous one, so the note held over from it
+    /// doesn't count until it's played again
+    awaiting_onset: bool,
+    pub is_matching: bool,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+const DEFAULT_DURATION: f32 = 1.0;
+
+impl Practice {
+    /// Reads a melody file with one note per line: a tab followed by an optional
+    /// duration in seconds (e.g. `-4 0.5`). Empty lines and lines starting with
+    /// `#` are skipped. Tabs may be written in any notation style.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut notes = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let tab = native_tab(fields.next().unwrap());
+            let duration = match fields.next() {
+                Some(field) => field
+                    .parse()
+                    .map_err(|_| format!("line {}: invalid duration", line_number + 1))?,
+                None => DEFAULT_DURATION,
+            };
+            notes.push(TargetNote { tab, duration });
+        }
+
+        if notes.is_empty() {
+            return Err("melody contains no notes".to_owned());
+        }
+
+        Ok(Self {
+            notes,
+            index: 0,
+            elapsed: 0.0,
+            matched_time: 0.0,
+            awaiting_onset: false,
+            is_matching: false,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    pub fn current_target(&self) -> Option<&TargetNote> {
+        self.notes.get(self.index)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index >= self.notes.len()
+    }
+
+    pub fn note_count(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// Advances the timer by `delta` seconds, comparing `played` (in harptabber's
+    /// notation) against the current target. A target counts as hit if it was
+    /// matched for at least half of its duration. `played` is empty during silence.
+    pub fn update(&mut self, delta: f32, played: &str) {
+        let target = match self.notes.get(self.index) {
+            Some(target) => target,
+            None => return,
+        };
+
+        if played != target.tab {
+            self.awaiting_onset = false;
+        }
+        self.is_matching = !self.awaiting_onset && played == target.tab;
+        if self.is_matching {
+            self.matched_time += delta;
+        }
+
+        self.elapsed += delta;
+        if self.elapsed >= target.duration {
+            if self.matched_time >= target.duration * 0.5 {
+                self.hits += 1;
+            } else {
+                self.misses += 1;
+            }
+            self.index += 1;
+            self.elapsed = 0.0;
+            self.matched_time = 0.0;
+            self.awaiting_onset = self
+                .notes
+                .get(self.index)
+                .map_or(false, |next| next.tab == target.tab);
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.index = 0;
+        self.elapsed = 0.0;
+        self.matched_time = 0.0;
+        self.awaiting_onset = false;
+        self.is_matching = false;
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
@@ -0,0 +1,86 @@
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// Highest frequency shown, enough for the upper holes and a few harmonics of the lower ones
+pub const MAX_FREQUENCY: f32 = 4000.0;
+/// Number of blocks kept, the oldest scrolls out on the left
+pub const COLUMNS: usize = 200;
+/// Magnitudes this far below full scale are drawn black
+const FLOOR_DB: f32 = -80.0;
+
+/// Computes the magnitude spectrum of analysis blocks.
+pub struct Analyser {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl Analyser {
+    pub fn new(size: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+            .collect();
+        Self {
+            fft,
+            window,
+            spectrum: vec![Complex::default(); size],
+        }
+    }
+
+    /// Returns a brightness in 0..=1 per FFT bin up to `MAX_FREQUENCY`. `signal`
+    /// must be as long as the size passed to `new`.
+    pub fn analyse(&mut self, signal: &[f32], sample_rate: usize) -> Vec<f32> {
+        for ((bin, sample), weight) in self.spectrum.iter_mut().zip(signal).zip(&self.window) {
+            *bin = Complex::new(sample * weight, 0.0);
+        }
+        self.fft.process(&mut self.spectrum);
+
+        // a full scale sine ends up at 0 dB
+        let window_sum: f32 = self.window.iter().sum();
+        let bin_width = sample_rate as f32 / self.spectrum.len() as f32;
+        let bin_count = ((MAX_FREQUENCY / bin_width) as usize).min(self.spectrum.len() / 2);
+        self.spectrum[..bin_count]
+            .iter()
+            .map(|bin| {
+                let db = 20.0 * (bin.norm() * 2.0 / window_sum).max(1e-9).log10();
+                (1.0 - db / FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// The spectrum of one analysis block.
+pub struct Column {
+    /// Brightness per FFT bin, see `Analyser::analyse`
+    pub levels: Vec<f32>,
+    /// Frequency the detector reported for this block
+    pub fundamental: Option<f32>,
+}
+
+/// Scrolling history of magnitude spectra, for seeing what the detector worked with.
+pub struct Spectrogram {
+    /// Distance in Hz between neighbouring entries of `Column::levels`
+    pub bin_width: f32,
+    /// Oldest first
+    pub columns: VecDeque<Column>,
+}
+
+impl Spectrogram {
+    pub fn new(size: usize, sample_rate: usize) -> Self {
+        Self {
+            bin_width: sample_rate as f32 / size as f32,
+            columns: VecDeque::with_capacity(COLUMNS + 1),
+        }
+    }
+
+    pub fn push(&mut self, column: Column) {
+        self.columns.push_back(column);
+        if self.columns.len() > COLUMNS {
+            self.columns.pop_front();
+        }
+    }
+}
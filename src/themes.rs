@@ -0,0 +1,104 @@
+use nannou::color::LinSrgb;
+use std::fs;
+use std::path::Path;
+
+pub const THEMES_PATH: &str = "themes.txt";
+
+/// A named set of colors applied to `Settings` all at once.
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    pub background: LinSrgb,
+    pub left: LinSrgb,
+    pub center: LinSrgb,
+    pub right: LinSrgb,
+    pub note: LinSrgb,
+}
+
+pub fn builtin_themes() -> Vec<Theme> {
+    vec![
+        Theme {
+            name: "dark".to_owned(),
+            background: LinSrgb::new(0.0, 0.0, 0.0),
+            left: LinSrgb::new(0.0, 0.1, 0.8),
+            center: LinSrgb::new(0.5, 0.1, 0.8),
+            right: LinSrgb::new(1.0, 0.1, 0.8),
+            note: LinSrgb::new(1.0, 1.0, 1.0),
+        },
+        Theme {
+            name: "light".to_owned(),
+            background: LinSrgb::new(1.0, 1.0, 1.0),
+            left: LinSrgb::new(0.0, 0.2, 0.6),
+            center: LinSrgb::new(0.1, 0.5, 0.2),
+            right: LinSrgb::new(0.7, 0.05, 0.05),
+            note: LinSrgb::new(0.0, 0.0, 0.0),
+        },
+        Theme {
+            name: "neon".to_owned(),
+            background: LinSrgb::new(0.01, 0.0, 0.03),
+            left: LinSrgb::new(0.0, 1.0, 0.9),
+            center: LinSrgb::new(1.0, 1.0, 0.0),
+            right: LinSrgb::new(1.0, 0.0, 0.6),
+            note: LinSrgb::new(0.7, 1.0, 0.2),
+        },
+    ]
+}
+
+/// Loads themes from a file made of sections like
+///
+/// ```text
+/// [sunset]
+/// background = 0.05 0.0 0.02
+/// left = 1.0 0.4 0.0
+/// center = 1.0 0.1 0.3
+/// right = 0.4 0.0 0.6
+/// note = 1.0 0.9 0.8
+/// ```
+///
+/// with linear RGB components. Colors left out of a section are taken from the
+/// dark theme.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Theme>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let default = builtin_themes().remove(0);
+    let mut themes: Vec<Theme> = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            themes.push(Theme {
+                name: name.trim().to_owned(),
+                ..default.clone()
+            });
+            continue;
+        }
+
+        let error = || format!("line {}: expected `name = r g b`", line_number + 1);
+        let theme = themes
+            .last_mut()
+            .ok_or_else(|| format!("line {}: color outside of a theme", line_number + 1))?;
+        let (key, value) = line.split_once('=').ok_or_else(error)?;
+        let components: Vec<f32> = value
+            .split_whitespace()
+            .map(|c| c.parse().ok())
+            .collect::<Option<_>>()
+            .ok_or_else(error)?;
+        if components.len() != 3 {
+            return Err(error());
+        }
+        let color = LinSrgb::new(components[0], components[1], components[2]);
+
+        match key.trim() {
+            "background" => theme.background = color,
+            "left" => theme.left = color,
+            "center" => theme.center = color,
+            "right" => theme.right = color,
+            "note" => theme.note = color,
+            other => return Err(format!("line {}: unknown color {}", line_number + 1, other)),
+        }
+    }
+    Ok(themes)
+}